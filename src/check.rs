@@ -0,0 +1,243 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use crate::checksum;
+use crate::drive::{as_secs, FileWrapper};
+use crate::link;
+use crate::remote::RemoteFile;
+
+/// A way the index, the local tree and the remote have drifted apart, as found by `rdrive check`.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub enum Issue {
+    /// A synced file that has been removed from the local tree.
+    MissingLocally { id: String, path: PathBuf },
+    /// An indexed file that no longer exists on the remote.
+    OrphanRow { id: String, path: PathBuf },
+    /// A local file whose content differs from the remote, although neither has changed since
+    /// it was last synced.
+    ChecksumMismatch { id: String, path: PathBuf },
+    /// An indexed file that has been renamed or moved on the remote, so is still synced to its
+    /// old path.
+    StalePath { id: String, path: PathBuf },
+    /// A local file that isn't in the index, so will be uploaded as a new file.
+    Unsynced { path: PathBuf },
+}
+
+impl fmt::Display for Issue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Issue::MissingLocally { id, path } => {
+                write!(f, "{} ({}) is missing locally", path.display(), id)
+            }
+            Issue::OrphanRow { id, path } => {
+                write!(f, "{} ({}) is no longer on the remote", path.display(), id)
+            }
+            Issue::ChecksumMismatch { id, path } => write!(
+                f,
+                "{} ({}) has different content to the remote",
+                path.display(),
+                id
+            ),
+            Issue::StalePath { id, path } => write!(
+                f,
+                "{} ({}) has been renamed or moved on the remote",
+                path.display(),
+                id
+            ),
+            Issue::Unsynced { path } => write!(f, "{} isn't synced", path.display()),
+        }
+    }
+}
+
+/// Compares the stored files with the remote listing and the local tree. Local content is only
+/// read for files whose size matches the remote, so the check stays cheap for large trees.
+pub fn find_issues(
+    stored_files: &[FileWrapper],
+    remote_files: &[RemoteFile],
+    local_files: &[FileWrapper],
+) -> io::Result<Vec<Issue>> {
+    let remote_files_by_id: HashMap<&str, &RemoteFile> = remote_files
+        .iter()
+        .map(|remote_file| (remote_file.id.as_str(), remote_file))
+        .collect();
+    let stored_paths: HashSet<&PathBuf> = stored_files.iter().map(|file| &file.path).collect();
+    let mut issues = Vec::new();
+    for stored_file in stored_files {
+        let id = stored_file.id.clone();
+        let path = stored_file.path.clone();
+        let remote_file = match remote_files_by_id.get(stored_file.id.as_str()) {
+            Some(remote_file) => remote_file,
+            None => {
+                issues.push(Issue::OrphanRow { id, path });
+                continue;
+            }
+        };
+        if stored_file.directory || stored_file.trashed || remote_file.trashed {
+            continue;
+        }
+        let metadata = match fs::symlink_metadata(&stored_file.path) {
+            Ok(metadata) => metadata,
+            Err(_) => {
+                if stored_file.last_accessed != SystemTime::UNIX_EPOCH {
+                    issues.push(Issue::MissingLocally { id, path });
+                }
+                continue;
+            }
+        };
+        if remote_file.name != stored_file.name || remote_file.parents != stored_file.parents {
+            issues.push(Issue::StalePath { id, path });
+            continue;
+        }
+        let is_unchanged = metadata.is_file()
+            && as_secs(metadata.modified()?) == as_secs(stored_file.last_accessed)
+            && remote_file.version == stored_file.version;
+        if is_unchanged
+            && stored_file.shortcut_target_id.is_none()
+            && stored_file.symlink_target.is_none()
+            && !link::is_google_native(&stored_file.mime_type)
+            && !matches_remote(remote_file, &stored_file.path, metadata.len())?
+        {
+            issues.push(Issue::ChecksumMismatch { id, path });
+        }
+    }
+    for local_file in local_files {
+        if !local_file.directory
+            && !stored_paths.contains(&local_file.path)
+            && !link::is_link_file(&local_file.path)
+        {
+            issues.push(Issue::Unsynced {
+                path: local_file.path.clone(),
+            });
+        }
+    }
+    Ok(issues)
+}
+
+/// Whether the local content matches the size and checksum reported by the remote. Remotes that
+/// report neither are assumed to match.
+fn matches_remote(remote_file: &RemoteFile, path: &Path, size: u64) -> io::Result<bool> {
+    if remote_file
+        .size
+        .is_some_and(|remote_size| remote_size != size)
+    {
+        return Ok(false);
+    }
+    match &remote_file.md5_checksum {
+        Some(md5_checksum) => Ok(checksum::md5_file(path)? == *md5_checksum),
+        None => Ok(true),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use super::*;
+
+    fn remote_file(id: &str, name: &str) -> RemoteFile {
+        RemoteFile {
+            id: id.to_string(),
+            name: name.to_string(),
+            parents: vec!["root".to_string()],
+            ..RemoteFile::test_default()
+        }
+    }
+
+    fn stored_file(remote_file: &RemoteFile, path: PathBuf) -> FileWrapper {
+        let last_accessed = fs::metadata(&path)
+            .and_then(|metadata| metadata.modified())
+            .unwrap_or(SystemTime::UNIX_EPOCH);
+        FileWrapper {
+            id: remote_file.id.clone(),
+            name: remote_file.name.clone(),
+            mime_type: remote_file.mime_type.clone(),
+            path,
+            last_modified: remote_file.modified_time,
+            last_accessed,
+            version: remote_file.version.clone(),
+            parents: remote_file.parents.clone(),
+            ..FileWrapper::test_default()
+        }
+    }
+
+    #[test]
+    fn find_issues_should_report_each_kind_of_divergence() {
+        let dir = env::temp_dir().join("rdrive-check-test");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("same.txt"), "abc").unwrap();
+        fs::write(dir.join("changed.txt"), "abd").unwrap();
+        fs::write(dir.join("moved.txt"), "abc").unwrap();
+        fs::write(dir.join("new.txt"), "abc").unwrap();
+        let same = RemoteFile {
+            size: Some(3),
+            md5_checksum: Some("900150983cd24fb0d6963f7d28e17f72".to_string()),
+            ..remote_file("same", "same.txt")
+        };
+        let changed = RemoteFile {
+            id: "changed".to_string(),
+            name: "changed.txt".to_string(),
+            ..same.clone()
+        };
+        let missing = remote_file("missing", "missing.txt");
+        let pending = remote_file("pending", "pending.txt");
+        let moved = remote_file("moved", "moved.txt");
+        let stored_files = vec![
+            stored_file(&same, dir.join("same.txt")),
+            stored_file(&changed, dir.join("changed.txt")),
+            stored_file(&pending, dir.join("pending.txt")),
+            FileWrapper {
+                last_accessed: SystemTime::now(),
+                ..stored_file(&missing, dir.join("missing.txt"))
+            },
+            stored_file(&moved, dir.join("moved.txt")),
+            stored_file(&remote_file("orphan", "orphan.txt"), dir.join("orphan.txt")),
+        ];
+        let remote_files = vec![
+            same,
+            changed,
+            missing,
+            pending,
+            RemoteFile {
+                parents: vec!["folder".to_string()],
+                ..moved
+            },
+        ];
+        let local_files = vec![
+            stored_file(&remote_files[0], dir.join("same.txt")),
+            stored_file(&remote_file("new", "new.txt"), dir.join("new.txt")),
+        ];
+
+        let issues = find_issues(&stored_files, &remote_files, &local_files).unwrap();
+
+        assert_eq!(
+            issues,
+            vec![
+                Issue::ChecksumMismatch {
+                    id: "changed".to_string(),
+                    path: dir.join("changed.txt"),
+                },
+                Issue::MissingLocally {
+                    id: "missing".to_string(),
+                    path: dir.join("missing.txt"),
+                },
+                Issue::StalePath {
+                    id: "moved".to_string(),
+                    path: dir.join("moved.txt"),
+                },
+                Issue::OrphanRow {
+                    id: "orphan".to_string(),
+                    path: dir.join("orphan.txt"),
+                },
+                Issue::Unsynced {
+                    path: dir.join("new.txt"),
+                },
+            ]
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
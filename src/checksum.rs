@@ -0,0 +1,54 @@
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+
+use md5::{Digest, Md5};
+
+/// The MD5 digest of a file's content as lowercase hex, as reported by Drive's `md5Checksum`.
+/// It is only used to compare local files with the checksums Drive keeps, never for security.
+pub fn md5_file(path: &Path) -> io::Result<String> {
+    let mut file = File::open(path)?;
+    let mut md5 = Md5::new();
+    let mut buffer = vec![0u8; 64 * 1024];
+    loop {
+        let read = file.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        md5.update(&buffer[..read]);
+    }
+    Ok(format!("{:x}", md5.finalize()))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs;
+
+    use super::*;
+
+    #[test]
+    fn md5_file_should_match_rfc_1321_test_suite() {
+        let path = env::temp_dir().join("rdrive-checksum-test");
+        for (content, expected) in [
+            ("", "d41d8cd98f00b204e9800998ecf8427e"),
+            ("abc", "900150983cd24fb0d6963f7d28e17f72"),
+            (
+                "abcdefghijklmnopqrstuvwxyz",
+                "c3fcd3d76192e4007dfb496cca67e13b",
+            ),
+        ] {
+            fs::write(&path, content).unwrap();
+            assert_eq!(md5_file(&path).unwrap(), expected);
+        }
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn md5_file_should_read_files_bigger_than_its_buffer() {
+        let path = env::temp_dir().join("rdrive-checksum-large-test");
+        fs::write(&path, vec![b'a'; 1_000_000]).unwrap();
+        assert_eq!(md5_file(&path).unwrap(), "7707d6ae4e027c70eea2a935c2296f21");
+        fs::remove_file(path).unwrap();
+    }
+}
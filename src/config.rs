@@ -0,0 +1,782 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs::create_dir_all;
+use std::io::{BufReader, BufWriter};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use std::{env, fs};
+
+use glob::Pattern;
+use log::error;
+use serde::{Deserialize, Serialize};
+
+use crate::error::{RdriveError, Result};
+use crate::export::{default_export, find_export_format, find_import_format, ExportFormat};
+use crate::link::LinkFormat;
+use crate::naming::NameProfile;
+
+#[derive(Default, Clone, Debug, Serialize, Deserialize)]
+struct StoredConfig {
+    exclude: Vec<String>,
+    include: Vec<String>,
+    root_dir: PathBuf,
+    #[serde(default)]
+    remote: RemoteConfig,
+    /// Configs written before exporting was added have no `export`, so nothing is exported
+    /// for them until it is set. New configs are written with the default formats.
+    #[serde(default)]
+    export: BTreeMap<String, String>,
+    #[serde(default)]
+    link_format: LinkFormat,
+    #[serde(default)]
+    import: BTreeMap<String, String>,
+    #[serde(default)]
+    shared_with_me: bool,
+    #[serde(default)]
+    name_profile: NameProfile,
+    #[serde(default)]
+    permissions: PermissionsConfig,
+    #[serde(default)]
+    symlinks: SymlinkPolicy,
+    #[serde(default)]
+    xattrs: bool,
+    #[serde(default)]
+    trash: TrashConfig,
+    #[serde(default)]
+    keep_forever: Vec<String>,
+    #[serde(default)]
+    mode: SyncMode,
+    #[serde(default)]
+    delete_remote: bool,
+    #[serde(default)]
+    conflict: ConflictPolicy,
+    #[serde(default)]
+    pairs: Vec<SyncPairConfig>,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum RemoteConfig {
+    Google {
+        #[serde(default)]
+        shared_drives: Vec<SharedDriveConfig>,
+    },
+    WebDav {
+        url: String,
+        username: String,
+        password: String,
+    },
+}
+
+impl Default for RemoteConfig {
+    fn default() -> Self {
+        RemoteConfig::Google {
+            shared_drives: Vec::new(),
+        }
+    }
+}
+
+/// A shared drive to sync into its own directory of the root directory.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct SharedDriveConfig {
+    pub id: String,
+    /// The directory to sync the drive to, defaulting to the drive's name.
+    #[serde(default)]
+    pub directory: Option<String>,
+}
+
+/// How the POSIX permissions of files are kept when they are synced.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct PermissionsConfig {
+    /// Whether to store the mode of uploaded files on the remote and reapply it on download.
+    #[serde(default)]
+    pub preserve: bool,
+    /// Whether to also store the owner and group names, which are only reapplied when rdrive is
+    /// allowed to change them.
+    #[serde(default)]
+    pub owner: bool,
+    /// The umask used for the mode of downloaded files that have no stored mode, in octal.
+    #[serde(default = "default_umask")]
+    pub umask: String,
+}
+
+impl Default for PermissionsConfig {
+    fn default() -> Self {
+        PermissionsConfig {
+            preserve: false,
+            owner: false,
+            umask: default_umask(),
+        }
+    }
+}
+
+fn default_umask() -> String {
+    "022".to_string()
+}
+
+/// Whether local files are moved to the trash rather than being overwritten, and for how long
+/// they are kept.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct TrashConfig {
+    #[serde(default = "default_trash_enabled")]
+    pub enabled: bool,
+    /// How many days files are kept in the trash, or forever if not set.
+    #[serde(default = "default_trash_max_age_days")]
+    pub max_age_days: Option<u64>,
+    /// How many megabytes the trash can grow to before the oldest files are deleted, or
+    /// unlimited if not set.
+    #[serde(default)]
+    pub max_size_mb: Option<u64>,
+}
+
+impl Default for TrashConfig {
+    fn default() -> Self {
+        TrashConfig {
+            enabled: default_trash_enabled(),
+            max_age_days: default_trash_max_age_days(),
+            max_size_mb: None,
+        }
+    }
+}
+
+fn default_trash_enabled() -> bool {
+    true
+}
+
+fn default_trash_max_age_days() -> Option<u64> {
+    Some(30)
+}
+
+/// Which directions files are synced in.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SyncMode {
+    /// Changes on either side are synced to the other.
+    #[default]
+    TwoWay,
+    /// The local directory is the source of truth, so files are never downloaded and remote
+    /// changes are replaced by the local file.
+    UploadOnly,
+    /// The local directory is a read only mirror of the remote, so local changes are reverted.
+    DownloadOnly,
+}
+
+/// Which copy is kept when a file has changed both locally and on the remote since it was last
+/// synced. The copy that isn't kept is still in the local trash or the remote's revisions.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ConflictPolicy {
+    #[default]
+    Remote,
+    Local,
+    /// The copy that was modified last.
+    Newer,
+}
+
+/// A remote folder synced to its own local directory, with its own rules.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct SyncPairConfig {
+    /// Names the pair's state database, so must be unique and only use letters, digits, `-` and
+    /// `_`.
+    pub name: String,
+    /// The path, such as `Work/Projects`, or ID of the remote folder to sync. The whole remote
+    /// is synced if not set.
+    #[serde(default)]
+    pub remote_folder: Option<String>,
+    pub root_dir: PathBuf,
+    #[serde(default)]
+    pub include: Vec<String>,
+    #[serde(default)]
+    pub exclude: Vec<String>,
+    /// Defaults to the top level mode.
+    #[serde(default)]
+    pub mode: Option<SyncMode>,
+    /// Defaults to the top level conflict policy.
+    #[serde(default)]
+    pub conflict: Option<ConflictPolicy>,
+    /// Defaults to the top level `delete_remote`.
+    #[serde(default)]
+    pub delete_remote: Option<bool>,
+}
+
+/// A parsed [`SyncPairConfig`].
+#[derive(Clone, Debug)]
+pub struct SyncPair {
+    pub name: String,
+    pub remote_folder: Option<String>,
+    pub root_dir: PathBuf,
+    pub include: Vec<Pattern>,
+    pub exclude: Vec<Pattern>,
+    pub mode: Option<SyncMode>,
+    pub conflict: Option<ConflictPolicy>,
+    pub delete_remote: Option<bool>,
+}
+
+/// What is done with symlinks found in the root directory.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SymlinkPolicy {
+    /// Symlinks are left out of the sync.
+    Skip,
+    /// Symlinks are synced as the file or directory they point to, skipping any that loop back
+    /// to a directory they are in.
+    #[default]
+    Follow,
+    /// Symlinks are stored on the remote as a record of where they point to, which is
+    /// recreated as a symlink on download.
+    Store,
+}
+
+#[derive(Clone)]
+pub struct Config {
+    /// The name of the sync pair this configuration is for, if sync pairs are configured.
+    pub name: Option<String>,
+    pub exclude: Vec<Pattern>,
+    pub include: Vec<Pattern>,
+    pub root_dir: PathBuf,
+    pub remote: RemoteConfig,
+    /// The format each Google-native MIME type is exported to. Google files without an entry are
+    /// left as links to the web editor.
+    pub export: HashMap<String, ExportFormat>,
+    pub link_format: LinkFormat,
+    /// The Google-native MIME type new local files are converted to on upload, keyed by their
+    /// lowercase extension.
+    pub import: HashMap<String, String>,
+    /// Whether to sync files other people have shared with the user.
+    pub shared_with_me: bool,
+    /// The rules Drive names are sanitised with before they are used as local file names.
+    pub name_profile: NameProfile,
+    /// Whether the mode of files is stored on the remote and reapplied on download.
+    pub preserve_permissions: bool,
+    /// Whether the owner and group names are stored and reapplied along with the mode.
+    pub preserve_owner: bool,
+    /// The umask for downloaded files that have no stored mode.
+    pub umask: u32,
+    pub symlinks: SymlinkPolicy,
+    /// Whether to write the ID and version of the remote file to extended attributes of each
+    /// synced file.
+    pub xattrs: bool,
+    /// Whether local files are moved to the trash before they are overwritten.
+    pub trash: bool,
+    /// How long files are kept in the trash.
+    pub trash_max_age: Option<Duration>,
+    /// How many bytes the trash can grow to before the oldest files are deleted.
+    pub trash_max_size: Option<u64>,
+    /// Files whose uploaded revisions are kept forever on the remote.
+    pub keep_forever: Vec<Pattern>,
+    pub mode: SyncMode,
+    /// Whether files deleted locally are deleted from the remote in upload only mode.
+    pub delete_remote: bool,
+    pub conflict: ConflictPolicy,
+    /// The path or ID of the remote folder synced to `root_dir`, or the whole remote if not set.
+    pub remote_folder: Option<String>,
+    /// The sync pairs to sync instead of `root_dir`, if any.
+    pub pairs: Vec<SyncPair>,
+}
+
+impl Config {
+    /// The configuration for each sync pair, which share every setting that isn't set per pair.
+    /// Without sync pairs, this is just the configuration itself.
+    pub fn into_pairs(self) -> Vec<Config> {
+        if self.pairs.is_empty() {
+            return vec![self];
+        }
+        self.pairs
+            .iter()
+            .map(|pair| Config {
+                name: Some(pair.name.clone()),
+                remote_folder: pair.remote_folder.clone(),
+                root_dir: pair.root_dir.clone(),
+                include: pair.include.clone(),
+                exclude: pair.exclude.clone(),
+                mode: pair.mode.unwrap_or(self.mode),
+                conflict: pair.conflict.unwrap_or(self.conflict),
+                delete_remote: pair.delete_remote.unwrap_or(self.delete_remote),
+                pairs: Vec::new(),
+                ..self.clone()
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+impl Config {
+    /// A two-way sync of the whole remote to `root_dir` with the trash on and every other option
+    /// off, for tests to override the fields they care about.
+    pub(crate) fn test_default(root_dir: PathBuf) -> Config {
+        Config {
+            name: None,
+            exclude: Vec::new(),
+            include: Vec::new(),
+            root_dir,
+            remote: RemoteConfig::default(),
+            export: HashMap::new(),
+            link_format: LinkFormat::default(),
+            import: HashMap::new(),
+            shared_with_me: false,
+            name_profile: NameProfile::default(),
+            preserve_permissions: false,
+            preserve_owner: false,
+            umask: 0o022,
+            symlinks: SymlinkPolicy::default(),
+            xattrs: false,
+            trash: true,
+            trash_max_age: None,
+            trash_max_size: None,
+            keep_forever: Vec::new(),
+            mode: SyncMode::TwoWay,
+            delete_remote: false,
+            conflict: ConflictPolicy::default(),
+            remote_folder: None,
+            pairs: Vec::new(),
+        }
+    }
+}
+
+pub fn get_config() -> Result<Config> {
+    let config_file = get_config_file()?;
+    if config_file.metadata()?.len() > 0 {
+        let config: StoredConfig = serde_json::from_reader(BufReader::new(&config_file))
+            .map_err(|error| RdriveError::Config(error.to_string()))?;
+        return Ok(Config {
+            name: None,
+            exclude: parse_patterns(&config.exclude)?,
+            include: parse_patterns(&config.include)?,
+            root_dir: config.root_dir,
+            remote: config.remote,
+            export: parse_export(&config.export)?,
+            link_format: config.link_format,
+            import: parse_import(&config.import)?,
+            shared_with_me: config.shared_with_me,
+            name_profile: config.name_profile,
+            preserve_permissions: config.permissions.preserve,
+            preserve_owner: config.permissions.owner,
+            umask: parse_umask(&config.permissions.umask)?,
+            symlinks: config.symlinks,
+            xattrs: config.xattrs,
+            trash: config.trash.enabled,
+            trash_max_age: config.trash.max_age_days.map(days_to_duration),
+            trash_max_size: config.trash.max_size_mb.map(|size| size * 1024 * 1024),
+            keep_forever: parse_patterns(&config.keep_forever)?,
+            mode: config.mode,
+            delete_remote: config.delete_remote,
+            conflict: config.conflict,
+            remote_folder: None,
+            pairs: parse_pairs(&config.pairs)?,
+        });
+    }
+    let default_root_dir = Path::new(&get_home_dir()?).join("rdrive");
+    let default_stored_config = StoredConfig {
+        exclude: Vec::new(),
+        include: Vec::new(),
+        root_dir: default_root_dir.clone(),
+        remote: RemoteConfig::default(),
+        export: default_export(),
+        link_format: LinkFormat::default(),
+        import: BTreeMap::new(),
+        shared_with_me: false,
+        name_profile: NameProfile::default(),
+        permissions: PermissionsConfig::default(),
+        symlinks: SymlinkPolicy::default(),
+        xattrs: false,
+        trash: TrashConfig::default(),
+        keep_forever: Vec::new(),
+        mode: SyncMode::default(),
+        delete_remote: false,
+        conflict: ConflictPolicy::default(),
+        pairs: Vec::new(),
+    };
+    let write_result =
+        serde_json::to_writer_pretty(BufWriter::new(&config_file), &default_stored_config);
+    if let Err(result) = write_result {
+        error!("{}", result);
+    }
+    Ok(Config {
+        name: None,
+        exclude: Vec::new(),
+        include: Vec::new(),
+        root_dir: default_root_dir.clone(),
+        remote: RemoteConfig::default(),
+        export: parse_export(&default_stored_config.export)?,
+        link_format: default_stored_config.link_format,
+        import: HashMap::new(),
+        shared_with_me: false,
+        name_profile: default_stored_config.name_profile,
+        preserve_permissions: false,
+        preserve_owner: false,
+        umask: parse_umask(&default_stored_config.permissions.umask)?,
+        symlinks: default_stored_config.symlinks,
+        xattrs: false,
+        trash: default_stored_config.trash.enabled,
+        trash_max_age: default_stored_config
+            .trash
+            .max_age_days
+            .map(days_to_duration),
+        trash_max_size: None,
+        keep_forever: Vec::new(),
+        mode: default_stored_config.mode,
+        delete_remote: default_stored_config.delete_remote,
+        conflict: default_stored_config.conflict,
+        remote_folder: None,
+        pairs: Vec::new(),
+    })
+}
+
+fn days_to_duration(days: u64) -> Duration {
+    Duration::from_secs(days * 24 * 60 * 60)
+}
+
+fn parse_patterns(patterns: &[String]) -> Result<Vec<Pattern>> {
+    patterns
+        .iter()
+        .map(|pattern| {
+            Pattern::new(pattern).map_err(|error| {
+                RdriveError::Config(format!("Invalid pattern {}: {}", pattern, error))
+            })
+        })
+        .collect()
+}
+
+/// Pairs must have unique names and root directories that don't contain each other, as a file
+/// in both would be synced by two pairs at once.
+fn parse_pairs(pairs: &[SyncPairConfig]) -> Result<Vec<SyncPair>> {
+    let mut names = HashSet::new();
+    let pairs = pairs
+        .iter()
+        .map(|pair| {
+            let is_valid_name = !pair.name.is_empty()
+                && pair
+                    .name
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+            if !is_valid_name {
+                return Err(RdriveError::Config(format!(
+                    "Invalid sync pair name {}",
+                    pair.name
+                )));
+            }
+            if !names.insert(&pair.name) {
+                return Err(RdriveError::Config(format!(
+                    "More than one sync pair is named {}",
+                    pair.name
+                )));
+            }
+            Ok(SyncPair {
+                name: pair.name.clone(),
+                remote_folder: pair.remote_folder.clone(),
+                root_dir: pair.root_dir.clone(),
+                include: parse_patterns(&pair.include)?,
+                exclude: parse_patterns(&pair.exclude)?,
+                mode: pair.mode,
+                conflict: pair.conflict,
+                delete_remote: pair.delete_remote,
+            })
+        })
+        .collect::<Result<Vec<SyncPair>>>()?;
+    for (index, pair) in pairs.iter().enumerate() {
+        for other in &pairs[index + 1..] {
+            if pair.root_dir.starts_with(&other.root_dir)
+                || other.root_dir.starts_with(&pair.root_dir)
+            {
+                return Err(RdriveError::Config(format!(
+                    "The root directories of sync pairs {} and {} overlap",
+                    pair.name, other.name
+                )));
+            }
+        }
+    }
+    Ok(pairs)
+}
+
+fn parse_export(export: &BTreeMap<String, String>) -> Result<HashMap<String, ExportFormat>> {
+    export
+        .iter()
+        .map(|(mime_type, extension)| {
+            find_export_format(mime_type, extension)
+                .map(|format| (mime_type.clone(), format))
+                .ok_or_else(|| {
+                    RdriveError::Config(format!(
+                        "Unsupported export format {} for {}",
+                        extension, mime_type
+                    ))
+                })
+        })
+        .collect()
+}
+
+fn parse_import(import: &BTreeMap<String, String>) -> Result<HashMap<String, String>> {
+    import
+        .iter()
+        .map(|(extension, mime_type)| {
+            find_import_format(mime_type, extension)
+                .map(|format| (format.extension.to_string(), mime_type.clone()))
+                .ok_or_else(|| {
+                    RdriveError::Config(format!(
+                        "Unsupported import of {} files as {}",
+                        extension, mime_type
+                    ))
+                })
+        })
+        .collect()
+}
+
+fn parse_umask(umask: &str) -> Result<u32> {
+    u32::from_str_radix(umask, 8)
+        .ok()
+        .filter(|umask| *umask <= 0o777)
+        .ok_or_else(|| RdriveError::Config(format!("Invalid umask {}", umask)))
+}
+
+fn get_env_var(name: &str) -> Result<String> {
+    env::var(name).map_err(|error| RdriveError::Config(format!("{} {}", name, error)))
+}
+
+pub fn get_base_data_path() -> Result<PathBuf> {
+    let data_path = match env::consts::OS {
+        "windows" => PathBuf::from(get_env_var("LOCALAPPDATA")?),
+        "linux" => match env::var("XDG_DATA_HOME") {
+            Ok(data_home) => PathBuf::from(data_home),
+            Err(_) => PathBuf::from(get_env_var("HOME")? + "/.local/share"),
+        },
+        "macos" => PathBuf::from(get_env_var("HOME")? + "/Library"),
+        _ => PathBuf::new(),
+    };
+    Ok(data_path.join("rdrive"))
+}
+
+fn get_home_dir() -> Result<String> {
+    match env::consts::OS {
+        "windows" => get_env_var("USERPROFILE"),
+        _ => get_env_var("HOME"),
+    }
+}
+
+fn get_config_file() -> Result<fs::File> {
+    let config_file = Path::new(&get_base_config_path()?)
+        .join("rdrive")
+        .join("config.json");
+    if !config_file.exists() {
+        create_dir_all(config_file.parent().unwrap()).map_err(|error| {
+            RdriveError::Config(format!(
+                "Failed to create config path {}. {}",
+                config_file.display(),
+                error
+            ))
+        })?;
+        return Ok(fs::File::create(config_file)?);
+    }
+    Ok(fs::OpenOptions::new()
+        .write(true)
+        .read(true)
+        .open(config_file)?)
+}
+
+fn get_base_config_path() -> Result<String> {
+    match env::consts::OS {
+        "windows" => get_env_var("LOCALAPPDATA"),
+        "linux" => match env::var("XDG_CONFIG_HOME") {
+            Ok(config_home) => Ok(config_home),
+            Err(_) => Ok(get_env_var("HOME")? + "/.config"),
+        },
+        "macos" => Ok(get_env_var("HOME")? + "/Library/Preferences"),
+        _ => Ok(String::new()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_patterns_should_parse_valid_patterns() {
+        let result = parse_patterns(&["**/*.tmp".to_string()]);
+        assert_eq!(result.unwrap(), vec![Pattern::new("**/*.tmp").unwrap()]);
+    }
+
+    #[test]
+    fn parse_patterns_should_return_config_error_for_invalid_pattern() {
+        let result = parse_patterns(&["[".to_string()]);
+        assert!(matches!(result, Err(RdriveError::Config(_))));
+    }
+
+    #[test]
+    fn remote_config_should_default_to_no_shared_drives() {
+        let remote: RemoteConfig = serde_json::from_str(r#"{"type": "google"}"#).unwrap();
+        assert_eq!(remote, RemoteConfig::default());
+    }
+
+    #[test]
+    fn remote_config_should_read_shared_drives() {
+        let remote: RemoteConfig = serde_json::from_str(
+            r#"{"type": "google", "shared_drives": [{"id": "0AB"}, {"id": "0CD", "directory": "Team"}]}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            remote,
+            RemoteConfig::Google {
+                shared_drives: vec![
+                    SharedDriveConfig {
+                        id: "0AB".to_string(),
+                        directory: None,
+                    },
+                    SharedDriveConfig {
+                        id: "0CD".to_string(),
+                        directory: Some("Team".to_string()),
+                    },
+                ],
+            }
+        );
+    }
+
+    #[test]
+    fn parse_export_should_parse_supported_formats() {
+        let export = BTreeMap::from([(
+            "application/vnd.google-apps.spreadsheet".to_string(),
+            "ODS".to_string(),
+        )]);
+        let result = parse_export(&export).unwrap();
+        assert_eq!(
+            result
+                .get("application/vnd.google-apps.spreadsheet")
+                .unwrap()
+                .mime_type,
+            "application/vnd.oasis.opendocument.spreadsheet"
+        );
+    }
+
+    #[test]
+    fn parse_export_should_return_config_error_for_unsupported_format() {
+        let export = BTreeMap::from([(
+            "application/vnd.google-apps.document".to_string(),
+            "xlsx".to_string(),
+        )]);
+        let result = parse_export(&export);
+        assert!(matches!(result, Err(RdriveError::Config(_))));
+    }
+
+    #[test]
+    fn parse_import_should_parse_supported_formats() {
+        let import = BTreeMap::from([(
+            "CSV".to_string(),
+            "application/vnd.google-apps.spreadsheet".to_string(),
+        )]);
+        let result = parse_import(&import).unwrap();
+        assert_eq!(
+            result.get("csv"),
+            Some(&"application/vnd.google-apps.spreadsheet".to_string())
+        );
+    }
+
+    #[test]
+    fn parse_import_should_return_config_error_for_unsupported_format() {
+        let import = BTreeMap::from([(
+            "pdf".to_string(),
+            "application/vnd.google-apps.document".to_string(),
+        )]);
+        let result = parse_import(&import);
+        assert!(matches!(result, Err(RdriveError::Config(_))));
+    }
+
+    #[test]
+    fn parse_umask_should_parse_octal_umask() {
+        assert_eq!(parse_umask("022").unwrap(), 0o22);
+        assert_eq!(parse_umask("077").unwrap(), 0o77);
+        assert!(matches!(parse_umask("999"), Err(RdriveError::Config(_))));
+        assert!(matches!(parse_umask("1000"), Err(RdriveError::Config(_))));
+    }
+
+    #[test]
+    fn stored_config_should_default_to_two_way_sync() {
+        let config: StoredConfig =
+            serde_json::from_str(r#"{"exclude": [], "include": [], "root_dir": ""}"#).unwrap();
+        assert_eq!(config.mode, SyncMode::TwoWay);
+        assert!(!config.delete_remote);
+        let config: StoredConfig = serde_json::from_str(
+            r#"{"exclude": [], "include": [], "root_dir": "", "mode": "upload-only"}"#,
+        )
+        .unwrap();
+        assert_eq!(config.mode, SyncMode::UploadOnly);
+    }
+
+    #[test]
+    fn stored_config_should_not_export_when_export_is_missing() {
+        let config: StoredConfig =
+            serde_json::from_str(r#"{"exclude": [], "include": [], "root_dir": ""}"#).unwrap();
+        assert!(config.export.is_empty());
+    }
+
+    #[test]
+    fn parse_pairs_should_reject_invalid_and_duplicate_names() {
+        let pair = SyncPairConfig {
+            name: "work".to_string(),
+            remote_folder: Some("Work/Projects".to_string()),
+            root_dir: PathBuf::from("/mnt/work"),
+            include: Vec::new(),
+            exclude: vec!["**/*.tmp".to_string()],
+            mode: None,
+            conflict: Some(ConflictPolicy::Newer),
+            delete_remote: None,
+        };
+        let pairs = parse_pairs(std::slice::from_ref(&pair)).unwrap();
+        assert_eq!(pairs[0].exclude, vec![Pattern::new("**/*.tmp").unwrap()]);
+        assert!(matches!(
+            parse_pairs(&[pair.clone(), pair.clone()]),
+            Err(RdriveError::Config(_))
+        ));
+        let invalid_pair = SyncPairConfig {
+            name: "../work".to_string(),
+            ..pair
+        };
+        assert!(matches!(
+            parse_pairs(&[invalid_pair]),
+            Err(RdriveError::Config(_))
+        ));
+    }
+
+    #[test]
+    fn parse_pairs_should_reject_overlapping_root_dirs() {
+        let pair = |name: &str, root_dir: &str| SyncPairConfig {
+            name: name.to_string(),
+            remote_folder: None,
+            root_dir: PathBuf::from(root_dir),
+            include: Vec::new(),
+            exclude: Vec::new(),
+            mode: None,
+            conflict: None,
+            delete_remote: None,
+        };
+        assert!(parse_pairs(&[pair("a", "/mnt/work"), pair("b", "/mnt/workspace")]).is_ok());
+        for (a, b) in [
+            ("/mnt/work", "/mnt/work"),
+            ("/mnt/work", "/mnt/work/projects"),
+            ("/mnt/work/projects", "/mnt/work"),
+        ] {
+            assert!(matches!(
+                parse_pairs(&[pair("a", a), pair("b", b)]),
+                Err(RdriveError::Config(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn into_pairs_should_default_pair_settings_to_top_level_values() {
+        let pair = |name: &str, delete_remote: Option<bool>| SyncPair {
+            name: name.to_string(),
+            remote_folder: None,
+            root_dir: PathBuf::from("/mnt").join(name),
+            include: Vec::new(),
+            exclude: Vec::new(),
+            mode: None,
+            conflict: None,
+            delete_remote,
+        };
+        let config = Config {
+            delete_remote: true,
+            pairs: vec![pair("a", None), pair("b", Some(false))],
+            ..Config::test_default(PathBuf::from("/mnt"))
+        };
+        let configs = config.into_pairs();
+        assert!(configs[0].delete_remote);
+        assert!(!configs[1].delete_remote);
+        assert_eq!(configs[1].root_dir, PathBuf::from("/mnt/b"));
+    }
+}
@@ -0,0 +1,170 @@
+use std::fs;
+use std::time::SystemTime;
+
+use log::warn;
+
+use crate::config::ConflictPolicy;
+use crate::drive::{as_secs, FileWrapper};
+
+/// A file that has changed on the remote is normally downloaded again, by resetting when it was
+/// last synced. When the local file has also changed since it was last synced, `policy` decides
+/// whether to keep when it was last synced instead, so the local file is uploaded over the remote
+/// one. Returns when `file_wrapper` should be treated as last synced, or `None` if there is no
+/// conflict.
+pub fn resolve(
+    policy: ConflictPolicy,
+    stored_file: &FileWrapper,
+    file_wrapper: &FileWrapper,
+) -> Option<SystemTime> {
+    let is_remote_changed = stored_file.version != file_wrapper.version
+        || stored_file.last_modified != file_wrapper.last_modified;
+    if !is_remote_changed
+        || file_wrapper.directory
+        || stored_file.path != file_wrapper.path
+        || as_secs(stored_file.last_accessed) == 0
+    {
+        return None;
+    }
+    let local_modified = fs::symlink_metadata(&file_wrapper.path)
+        .ok()
+        .filter(|metadata| metadata.is_file())
+        .and_then(|metadata| metadata.modified().ok())?;
+    if as_secs(local_modified) == as_secs(stored_file.last_accessed) {
+        return None;
+    }
+    let keep_local = match policy {
+        ConflictPolicy::Remote => false,
+        ConflictPolicy::Local => true,
+        ConflictPolicy::Newer => local_modified > SystemTime::from(file_wrapper.last_modified),
+    };
+    warn!(
+        "{} has changed both locally and on the remote, keeping the {} copy",
+        file_wrapper.path.display(),
+        if keep_local { "local" } else { "remote" }
+    );
+    if keep_local {
+        Some(stored_file.last_accessed)
+    } else {
+        Some(SystemTime::UNIX_EPOCH)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs::File;
+    use std::path::PathBuf;
+    use std::time::Duration;
+
+    use chrono::{DateTime, Utc};
+
+    use super::*;
+
+    const HOUR: Duration = Duration::from_secs(60 * 60);
+
+    /// A stored file synced a day ago, with a local copy modified `local_age` ago.
+    fn stored_file(name: &str, local_age: Duration) -> FileWrapper {
+        let path = env::temp_dir().join(name);
+        fs::write(&path, "content").unwrap();
+        let now = SystemTime::now();
+        File::options()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_modified(now - local_age)
+            .unwrap();
+        FileWrapper {
+            id: name.to_string(),
+            name: name.to_string(),
+            path,
+            last_modified: DateTime::<Utc>::from(now - 24 * HOUR).into(),
+            last_accessed: now - 24 * HOUR,
+            version: Some("1".to_string()),
+            parents: vec!["root".to_string()],
+            ..FileWrapper::test_default()
+        }
+    }
+
+    /// The same file as listed after being changed on the remote `remote_age` ago.
+    fn changed_on_remote(stored_file: &FileWrapper, remote_age: Duration) -> FileWrapper {
+        FileWrapper {
+            version: Some("2".to_string()),
+            last_modified: DateTime::<Utc>::from(SystemTime::now() - remote_age).into(),
+            last_accessed: SystemTime::UNIX_EPOCH,
+            ..stored_file.clone()
+        }
+    }
+
+    fn remove(path: PathBuf) {
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn resolve_should_find_no_conflict_unless_both_copies_changed() {
+        let stored_file = stored_file("rdrive-conflict-none-test", 24 * HOUR);
+        assert_eq!(
+            resolve(
+                ConflictPolicy::Local,
+                &stored_file,
+                &changed_on_remote(&stored_file, HOUR)
+            ),
+            None
+        );
+        let locally_changed = stored_file.clone();
+        File::options()
+            .write(true)
+            .open(&locally_changed.path)
+            .unwrap()
+            .set_modified(SystemTime::now())
+            .unwrap();
+        assert_eq!(
+            resolve(ConflictPolicy::Local, &stored_file, &locally_changed),
+            None
+        );
+        remove(stored_file.path);
+    }
+
+    #[test]
+    fn resolve_should_download_remote_copy_for_remote_policy() {
+        let stored_file = stored_file("rdrive-conflict-remote-test", HOUR);
+        let file_wrapper = changed_on_remote(&stored_file, 2 * HOUR);
+        assert_eq!(
+            resolve(ConflictPolicy::Remote, &stored_file, &file_wrapper),
+            Some(SystemTime::UNIX_EPOCH)
+        );
+        remove(stored_file.path);
+    }
+
+    #[test]
+    fn resolve_should_keep_local_copy_for_local_policy() {
+        let stored_file = stored_file("rdrive-conflict-local-test", 2 * HOUR);
+        let file_wrapper = changed_on_remote(&stored_file, HOUR);
+        assert_eq!(
+            resolve(ConflictPolicy::Local, &stored_file, &file_wrapper),
+            Some(stored_file.last_accessed)
+        );
+        remove(stored_file.path);
+    }
+
+    #[test]
+    fn resolve_should_keep_copy_modified_last_for_newer_policy() {
+        let stored_file = stored_file("rdrive-conflict-newer-test", 2 * HOUR);
+        assert_eq!(
+            resolve(
+                ConflictPolicy::Newer,
+                &stored_file,
+                &changed_on_remote(&stored_file, 3 * HOUR)
+            ),
+            Some(stored_file.last_accessed)
+        );
+        assert_eq!(
+            resolve(
+                ConflictPolicy::Newer,
+                &stored_file,
+                &changed_on_remote(&stored_file, HOUR)
+            ),
+            Some(SystemTime::UNIX_EPOCH)
+        );
+        remove(stored_file.path);
+    }
+}
@@ -1,11 +1,44 @@
-use std::path::PathBuf;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
 use std::time::SystemTime;
 
-use chrono::{DateTime, Local};
+use chrono::{DateTime, FixedOffset, Local};
 use log::{debug, error};
+use rusqlite::types::Type;
 use rusqlite::{named_params, Connection, Error, Row, Statement};
 
+use crate::config::get_base_data_path;
 use crate::drive::FileWrapper;
+use crate::error::RdriveError;
+use crate::migrations;
+use crate::permissions::Permissions;
+
+pub fn get_db_connection() -> crate::error::Result<Connection> {
+    open_db("rdrive.db")
+}
+
+/// Each sync pair keeps its state in a database of its own, so pairs never see each other's
+/// files.
+pub fn get_pair_db_connection(name: &str) -> crate::error::Result<Connection> {
+    open_db(&format!("rdrive-{}.db", name))
+}
+
+fn open_db(file_name: &str) -> crate::error::Result<Connection> {
+    let db_file = &get_base_data_path()?.join(file_name);
+    fs::create_dir_all(db_file.parent().unwrap())?;
+    Ok(Connection::open(db_file)?)
+}
+
+/// Paths are stored as text, so a path that isn't valid UTF-8 can't be stored or looked up.
+fn path_to_str(path: &Path) -> Result<&str, Error> {
+    path.to_str().ok_or_else(|| {
+        Error::ToSqlConversionFailure(Box::new(RdriveError::Path(
+            path.to_path_buf(),
+            "Path isn't valid UTF-8".to_string(),
+        )))
+    })
+}
 
 pub struct DbContext {
     conn: Connection,
@@ -16,62 +49,81 @@ impl DbContext {
         DbContext { conn }
     }
 
-    pub fn init(&self) -> Result<(), Error> {
-        self.conn.execute(
-            "CREATE TABLE IF NOT EXISTS file (
-                id TEXT PRIMARY KEY,
-                name TEXT NOT NULL,
-                mime_type TEXT NOT NULL,
-                path TEXT NOT NULL,
-                directory INTEGER NOT NULL,
-                web_view_link TEXT,
-                owned_by_me INTEGER NOT NULL,
-                last_modified TEXT NOT NULL,
-                last_accessed TEXT NOT NULL,
-                trashed INTEGER NOT NULL
-            )",
-            [],
-        )?;
-        Ok(())
+    /// Opens the database in the data directory.
+    pub fn open() -> crate::error::Result<Self> {
+        Ok(DbContext::new(get_db_connection()?))
+    }
+
+    /// Opens the database of the sync pair `name` in the data directory.
+    pub fn open_pair(name: &str) -> crate::error::Result<Self> {
+        Ok(DbContext::new(get_pair_db_connection(name)?))
+    }
+
+    pub fn init(&self) -> crate::error::Result<()> {
+        migrations::migrate(&self.conn)
     }
 
     pub fn store_file(&self, file_wrapper: &FileWrapper) -> Result<(), Error> {
         let last_accessed: SystemTime = file_wrapper.last_accessed;
         let last_accessed_converted: DateTime<Local> = DateTime::from(last_accessed);
-        let stored_file = self.get_file(&file_wrapper.id);
+        let stored_file = self.get_file(&file_wrapper.id)?;
+        self.store_parent_ids(&file_wrapper.id, &file_wrapper.parents)?;
         if stored_file.is_some()
             && stored_file.as_ref().unwrap().last_modified == file_wrapper.last_modified
+            && stored_file.as_ref().unwrap().version == file_wrapper.version
+            && stored_file.as_ref().unwrap().path == file_wrapper.path
+            && stored_file.as_ref().unwrap().can_edit == file_wrapper.can_edit
+            && stored_file.as_ref().unwrap().shortcut_target_id == file_wrapper.shortcut_target_id
+            && stored_file.as_ref().unwrap().permissions == file_wrapper.permissions
+            && stored_file.as_ref().unwrap().symlink_target == file_wrapper.symlink_target
         {
             return Ok(());
         }
         let mut statement: Statement = if stored_file.is_some() {
-            self.conn.prepare("UPDATE file SET name = :name, mime_type = :mime_type, path = :path, directory = :directory, web_view_link = :web_view_link, owned_by_me = :owned_by_me, last_modified = :last_modified, last_accessed = :last_accessed, trashed = :trashed WHERE id = :id")?
+            self.conn.prepare("UPDATE file SET name = :name, mime_type = :mime_type, path = :path, directory = :directory, web_view_link = :web_view_link, owned_by_me = :owned_by_me, last_modified = :last_modified, last_accessed = :last_accessed, trashed = :trashed, version = :version, can_edit = :can_edit, shortcut_target_id = :shortcut_target_id, mode = :mode, owner = :owner, group_name = :group_name, symlink_target = :symlink_target WHERE id = :id")?
         } else {
-            self.conn.prepare("INSERT INTO file (id, name, mime_type, path, directory, web_view_link, owned_by_me, last_modified, last_accessed, trashed) VALUES (:id, :name, :mime_type, :path, :directory, :web_view_link, :owned_by_me, :last_modified, :last_accessed, :trashed)")?
+            self.conn.prepare("INSERT INTO file (id, name, mime_type, path, directory, web_view_link, owned_by_me, last_modified, last_accessed, trashed, version, can_edit, shortcut_target_id, mode, owner, group_name, symlink_target) VALUES (:id, :name, :mime_type, :path, :directory, :web_view_link, :owned_by_me, :last_modified, :last_accessed, :trashed, :version, :can_edit, :shortcut_target_id, :mode, :owner, :group_name, :symlink_target)")?
         };
         statement.execute(named_params! {
             ":id": &file_wrapper.id,
             ":name": &file_wrapper.name,
             ":mime_type": &file_wrapper.mime_type,
-            ":path": &file_wrapper.path.to_str().unwrap(),
+            ":path": path_to_str(&file_wrapper.path)?,
             ":directory": &file_wrapper.directory,
             ":web_view_link": &file_wrapper.web_view_link,
             ":owned_by_me": &file_wrapper.owned_by_me,
             ":last_modified": &file_wrapper.last_modified.to_rfc3339(),
             ":last_accessed": &last_accessed_converted.to_rfc3339(),
-            ":trashed": &file_wrapper.trashed
+            ":trashed": &file_wrapper.trashed,
+            ":version": &file_wrapper.version,
+            ":can_edit": &file_wrapper.can_edit,
+            ":shortcut_target_id": &file_wrapper.shortcut_target_id,
+            ":mode": &file_wrapper.permissions.mode,
+            ":owner": &file_wrapper.permissions.owner,
+            ":group_name": &file_wrapper.permissions.group,
+            ":symlink_target": &file_wrapper.symlink_target
         })?;
         Ok(())
     }
 
-    pub fn get_file(&self, id: &String) -> Option<FileWrapper> {
+    pub fn get_file(&self, id: &String) -> Result<Option<FileWrapper>, Error> {
         let mut statement = self
             .conn
-            .prepare("SELECT * FROM file where id = :id LIMIT 1")
-            .unwrap();
-        let mut rows = statement.query(&[(":id", &id)]).unwrap();
-        let result = rows.next().unwrap();
-        result.map(DbContext::convert_to_file_wrapper)
+            .prepare("SELECT * FROM file where id = :id LIMIT 1")?;
+        let mut rows = statement.query(&[(":id", &id)])?;
+        rows.next()?
+            .map(|row| self.convert_to_file_wrapper(row))
+            .transpose()
+    }
+
+    pub fn get_file_by_path(&self, path: &Path) -> Result<Option<FileWrapper>, Error> {
+        let mut statement = self
+            .conn
+            .prepare("SELECT * FROM file WHERE path = :path AND trashed = 0 LIMIT 1")?;
+        let mut rows = statement.query(&[(":path", path_to_str(path)?)])?;
+        rows.next()?
+            .map(|row| self.convert_to_file_wrapper(row))
+            .transpose()
     }
 
     pub fn get_all_files(&self) -> Result<Vec<FileWrapper>, Error> {
@@ -79,27 +131,204 @@ impl DbContext {
         let mut rows = statement.query([])?;
         let mut files = Vec::new();
         while let Some(row) = rows.next()? {
-            files.push(DbContext::convert_to_file_wrapper(row));
+            files.push(self.convert_to_file_wrapper(row)?);
         }
         Ok(files)
     }
 
-    fn convert_to_file_wrapper(row: &Row) -> FileWrapper {
-        let path: String = row.get(3).unwrap();
-        let last_changed: String = row.get(7).unwrap();
-        let last_accessed: String = row.get(8).unwrap();
-        FileWrapper {
-            id: row.get(0).unwrap(),
-            name: row.get(1).unwrap(),
-            mime_type: row.get(2).unwrap(),
-            path: PathBuf::from(path),
-            directory: row.get(4).unwrap(),
-            web_view_link: row.get(5).unwrap(),
-            owned_by_me: row.get(6).unwrap(),
-            last_modified: DateTime::parse_from_rfc3339(&last_changed).unwrap(),
-            last_accessed: SystemTime::from(DateTime::parse_from_rfc3339(&last_accessed).unwrap()),
-            trashed: row.get(9).unwrap(),
+    /// Files whose parents include `parent_id`, so a folder's contents can be found without
+    /// going back to the remote.
+    pub fn get_children(&self, parent_id: &str) -> Result<Vec<FileWrapper>, Error> {
+        let mut statement = self.conn.prepare(
+            "SELECT file.* FROM file INNER JOIN file_parent ON file_parent.file_id = file.id WHERE file_parent.parent_id = :parent_id ORDER BY file.name",
+        )?;
+        let mut rows = statement.query(&[(":parent_id", &parent_id)])?;
+        let mut files = Vec::new();
+        while let Some(row) = rows.next()? {
+            files.push(self.convert_to_file_wrapper(row)?);
+        }
+        Ok(files)
+    }
+
+    pub fn get_parent_ids(&self, id: &str) -> Result<Vec<String>, Error> {
+        let mut statement = self
+            .conn
+            .prepare("SELECT parent_id FROM file_parent WHERE file_id = :id ORDER BY position")?;
+        let rows = statement.query_map(&[(":id", &id)], |row| row.get(0))?;
+        rows.collect()
+    }
+
+    /// Forgets every stored file, leaving what is known about imports and local names.
+    pub fn clear_files(&self) -> Result<(), Error> {
+        self.conn
+            .execute_batch("DELETE FROM file; DELETE FROM file_parent;")
+    }
+
+    /// Forgets a single file, such as one that no longer exists on the remote, along with its
+    /// parents, name suffix and remote name, so nothing is left behind if only some are deleted.
+    pub fn delete_file(&self, id: &str) -> Result<(), Error> {
+        self.transaction(|| {
+            self.conn
+                .execute("DELETE FROM file WHERE id = :id", &[(":id", &id)])?;
+            for table in ["file_parent", "name_suffix", "remote_name"] {
+                self.conn.execute(
+                    &format!("DELETE FROM {} WHERE file_id = :id", table),
+                    &[(":id", &id)],
+                )?;
+            }
+            Ok(())
+        })
+    }
+
+    fn store_parent_ids(&self, id: &str, parent_ids: &[String]) -> Result<(), Error> {
+        if self.get_parent_ids(id)? == parent_ids {
+            return Ok(());
         }
+        self.conn.execute(
+            "DELETE FROM file_parent WHERE file_id = :id",
+            &[(":id", &id)],
+        )?;
+        let mut statement = self.conn.prepare(
+            "INSERT INTO file_parent (file_id, parent_id, position) VALUES (:file_id, :parent_id, :position)",
+        )?;
+        for (position, parent_id) in parent_ids.iter().enumerate() {
+            statement.execute(named_params! {
+                ":file_id": id,
+                ":parent_id": parent_id,
+                ":position": position as i64
+            })?;
+        }
+        Ok(())
+    }
+
+    /// Records that the local file at `path` was converted to the Google file `id` on upload.
+    pub fn store_import(&self, id: &str, path: &Path) -> Result<(), Error> {
+        self.conn.execute(
+            "INSERT OR REPLACE INTO import (file_id, path) VALUES (:file_id, :path)",
+            named_params! {
+                ":file_id": id,
+                ":path": path_to_str(path)?
+            },
+        )?;
+        Ok(())
+    }
+
+    pub fn get_import_path(&self, id: &str) -> Result<Option<PathBuf>, Error> {
+        let mut statement = self
+            .conn
+            .prepare("SELECT path FROM import WHERE file_id = :file_id")?;
+        let mut rows = statement.query(&[(":file_id", &id)])?;
+        rows.next()?
+            .map(|row| row.get::<_, String>(0).map(PathBuf::from))
+            .transpose()
+    }
+
+    pub fn get_imports(&self) -> Result<HashMap<String, PathBuf>, Error> {
+        let mut statement = self.conn.prepare("SELECT file_id, path FROM import")?;
+        let rows = statement.query_map([], |row| {
+            Ok((row.get(0)?, PathBuf::from(row.get::<_, String>(1)?)))
+        })?;
+        rows.collect()
+    }
+
+    /// The suffixes given to files that share a name with other files in the same folder, so each
+    /// keeps the same local name between syncs.
+    pub fn get_name_suffixes(&self) -> Result<HashMap<String, usize>, Error> {
+        let mut statement = self
+            .conn
+            .prepare("SELECT file_id, suffix FROM name_suffix")?;
+        let rows = statement.query_map([], |row| {
+            let suffix: i64 = row.get(1)?;
+            Ok((row.get(0)?, suffix as usize))
+        })?;
+        rows.collect()
+    }
+
+    pub fn store_name_suffix(&self, id: &str, suffix: usize) -> Result<(), Error> {
+        self.conn.execute(
+            "INSERT OR REPLACE INTO name_suffix (file_id, suffix) VALUES (:file_id, :suffix)",
+            named_params! {
+                ":file_id": id,
+                ":suffix": suffix as i64
+            },
+        )?;
+        Ok(())
+    }
+
+    /// The Drive names of files whose local names had to be sanitised, with the local path each
+    /// was synced to, keyed by file ID.
+    pub fn get_remote_names(&self) -> Result<HashMap<String, (PathBuf, String)>, Error> {
+        let mut statement = self
+            .conn
+            .prepare("SELECT file_id, path, name FROM remote_name")?;
+        let rows = statement.query_map([], |row| {
+            let path: String = row.get(1)?;
+            Ok((row.get(0)?, (PathBuf::from(path), row.get(2)?)))
+        })?;
+        rows.collect()
+    }
+
+    /// The Drive name of the file synced to `path`, if its local name had to be sanitised.
+    pub fn get_remote_name(&self, path: &Path) -> Result<Option<String>, Error> {
+        let mut statement = self
+            .conn
+            .prepare("SELECT name FROM remote_name WHERE path = :path")?;
+        let mut rows = statement.query(&[(":path", path_to_str(path)?)])?;
+        rows.next()?.map(|row| row.get(0)).transpose()
+    }
+
+    pub fn store_remote_name(&self, id: &str, path: &Path, name: &str) -> Result<(), Error> {
+        self.conn.execute(
+            "INSERT OR REPLACE INTO remote_name (file_id, path, name) VALUES (:file_id, :path, :name)",
+            named_params! {
+                ":file_id": id,
+                ":path": path_to_str(path)?,
+                ":name": name
+            },
+        )?;
+        Ok(())
+    }
+
+    pub fn delete_remote_name(&self, id: &str) -> Result<(), Error> {
+        self.conn.execute(
+            "DELETE FROM remote_name WHERE file_id = :file_id",
+            named_params! {":file_id": id},
+        )?;
+        Ok(())
+    }
+
+    fn convert_to_file_wrapper(&self, row: &Row) -> Result<FileWrapper, Error> {
+        let id: String = row.get(0)?;
+        let path: String = row.get(3)?;
+        let last_changed: String = row.get(7)?;
+        let last_accessed: String = row.get(8)?;
+        Ok(FileWrapper {
+            parents: self.get_parent_ids(&id)?,
+            can_edit: row.get(11)?,
+            shortcut_target_id: row.get(12)?,
+            permissions: Permissions {
+                mode: row.get(13)?,
+                owner: row.get(14)?,
+                group: row.get(15)?,
+            },
+            symlink_target: row.get(16)?,
+            id,
+            name: row.get(1)?,
+            mime_type: row.get(2)?,
+            path: PathBuf::from(path),
+            directory: row.get(4)?,
+            web_view_link: row.get(5)?,
+            owned_by_me: row.get(6)?,
+            last_modified: DbContext::parse_date_time(7, &last_changed)?,
+            last_accessed: SystemTime::from(DbContext::parse_date_time(8, &last_accessed)?),
+            trashed: row.get(9)?,
+            version: row.get(10)?,
+        })
+    }
+
+    fn parse_date_time(index: usize, value: &str) -> Result<DateTime<FixedOffset>, Error> {
+        DateTime::parse_from_rfc3339(value)
+            .map_err(|error| Error::FromSqlConversionFailure(index, Type::Text, Box::new(error)))
     }
 
     pub fn update_last_accessed(
@@ -172,7 +401,10 @@ mod tests {
             [],
             |row| -> Result<String> { row.get(0) },
         );
-        assert_eq!(table, Ok("CREATE TABLE file (\n                id TEXT PRIMARY KEY,\n                name TEXT NOT NULL,\n                mime_type TEXT NOT NULL,\n                path TEXT NOT NULL,\n                directory INTEGER NOT NULL,\n                web_view_link TEXT,\n                owned_by_me INTEGER NOT NULL,\n                last_modified TEXT NOT NULL,\n                last_accessed TEXT NOT NULL,\n                trashed INTEGER NOT NULL\n            )".to_string()));
+        assert_eq!(table, Ok("CREATE TABLE file (\n                id TEXT PRIMARY KEY,\n                name TEXT NOT NULL,\n                mime_type TEXT NOT NULL,\n                path TEXT NOT NULL,\n                directory INTEGER NOT NULL,\n                web_view_link TEXT,\n                owned_by_me INTEGER NOT NULL,\n                last_modified TEXT NOT NULL,\n                last_accessed TEXT NOT NULL,\n                trashed INTEGER NOT NULL\n            , version TEXT, can_edit INTEGER NOT NULL DEFAULT 1, shortcut_target_id TEXT, mode INTEGER, owner TEXT, group_name TEXT, symlink_target TEXT)".to_string()));
+        let user_version: Result<i32> =
+            connection.pragma_query_value(None, "user_version", |row| row.get(0));
+        assert_eq!(user_version, Ok(migrations::latest_version()));
     }
 
     #[test]
@@ -195,6 +427,12 @@ mod tests {
             last_modified: DateTime::from(Utc::now()),
             last_accessed: SystemTime::from(Utc::now()),
             trashed: false,
+            version: None,
+            parents: Vec::new(),
+            can_edit: true,
+            shortcut_target_id: None,
+            permissions: Permissions::default(),
+            symlink_target: None,
         };
         let result = dbcontext.store_file(&expected_file_wrapper);
         assert!(result.is_ok());
@@ -219,6 +457,16 @@ mod tests {
                         DateTime::parse_from_rfc3339(&last_accessed).unwrap(),
                     ),
                     trashed: row.get(9).unwrap(),
+                    version: row.get(10).unwrap(),
+                    parents: Vec::new(),
+                    can_edit: row.get(11).unwrap(),
+                    shortcut_target_id: row.get(12).unwrap(),
+                    permissions: Permissions {
+                        mode: row.get(13).unwrap(),
+                        owner: row.get(14).unwrap(),
+                        group: row.get(15).unwrap(),
+                    },
+                    symlink_target: row.get(16).unwrap(),
                 })
             },
         );
@@ -245,6 +493,12 @@ mod tests {
             last_modified: DateTime::from(Utc::now()),
             last_accessed: SystemTime::from(Utc::now()),
             trashed: false,
+            version: None,
+            parents: Vec::new(),
+            can_edit: true,
+            shortcut_target_id: None,
+            permissions: Permissions::default(),
+            symlink_target: None,
         };
         let updated_file_wrapper = FileWrapper {
             id: original_file_wrapper.id.clone(),
@@ -259,6 +513,12 @@ mod tests {
                 Utc::now().with_minute(Utc::now().minute() + 1).unwrap(),
             ),
             trashed: true,
+            version: Some("version".to_string()),
+            parents: Vec::new(),
+            can_edit: true,
+            shortcut_target_id: None,
+            permissions: Permissions::default(),
+            symlink_target: None,
         };
         insert_file_wrapper(&connection, &original_file_wrapper);
         let result = dbcontext.store_file(&updated_file_wrapper);
@@ -284,6 +544,16 @@ mod tests {
                         DateTime::parse_from_rfc3339(&last_accessed).unwrap(),
                     ),
                     trashed: row.get(9).unwrap(),
+                    version: row.get(10).unwrap(),
+                    parents: Vec::new(),
+                    can_edit: row.get(11).unwrap(),
+                    shortcut_target_id: row.get(12).unwrap(),
+                    permissions: Permissions {
+                        mode: row.get(13).unwrap(),
+                        owner: row.get(14).unwrap(),
+                        group: row.get(15).unwrap(),
+                    },
+                    symlink_target: row.get(16).unwrap(),
                 })
             },
         );
@@ -310,18 +580,30 @@ mod tests {
             last_modified: DateTime::from(Utc::now()),
             last_accessed: SystemTime::from(Utc::now()),
             trashed: false,
+            version: None,
+            parents: Vec::new(),
+            can_edit: true,
+            shortcut_target_id: None,
+            permissions: Permissions::default(),
+            symlink_target: None,
         };
         let updated_file_wrapper = FileWrapper {
             id: original_file_wrapper.id.clone(),
             name: "updated name".to_string(),
             mime_type: "updated mime_type".to_string(),
-            path: PathBuf::from("src"),
+            path: original_file_wrapper.path.clone(),
             directory: true,
             web_view_link: Some("updated web_view_link".to_string()),
             owned_by_me: false,
             last_modified: original_file_wrapper.last_modified,
             last_accessed: SystemTime::from(Utc::now() + Duration::minutes(1)),
             trashed: true,
+            version: original_file_wrapper.version.clone(),
+            parents: Vec::new(),
+            can_edit: true,
+            shortcut_target_id: None,
+            permissions: Permissions::default(),
+            symlink_target: None,
         };
         insert_file_wrapper(&connection, &original_file_wrapper);
         let result = dbcontext.store_file(&updated_file_wrapper);
@@ -347,12 +629,430 @@ mod tests {
                         DateTime::parse_from_rfc3339(&last_accessed).unwrap(),
                     ),
                     trashed: row.get(9).unwrap(),
+                    version: row.get(10).unwrap(),
+                    parents: Vec::new(),
+                    can_edit: row.get(11).unwrap(),
+                    shortcut_target_id: row.get(12).unwrap(),
+                    permissions: Permissions {
+                        mode: row.get(13).unwrap(),
+                        owner: row.get(14).unwrap(),
+                        group: row.get(15).unwrap(),
+                    },
+                    symlink_target: row.get(16).unwrap(),
                 })
             },
         );
         assert_eq!(actual_file_wrapper.unwrap(), original_file_wrapper);
     }
 
+    #[test]
+    #[serial]
+    fn store_file_should_update_stored_file_details_if_path_is_different() {
+        delete_db();
+        let dbcontext_connection = get_connection();
+        let dbcontext = DbContext::new(dbcontext_connection);
+        let init_result = dbcontext.init();
+        assert!(init_result.is_ok());
+        let original_file_wrapper = FileWrapper {
+            id: "id".to_string(),
+            name: "name".to_string(),
+            mime_type: "application/vnd.google-apps.document".to_string(),
+            path: PathBuf::from("name"),
+            directory: false,
+            web_view_link: None,
+            owned_by_me: true,
+            last_modified: DateTime::from(Utc::now()),
+            last_accessed: SystemTime::from(Utc::now()),
+            trashed: false,
+            version: Some("version".to_string()),
+            parents: Vec::new(),
+            can_edit: true,
+            shortcut_target_id: None,
+            permissions: Permissions::default(),
+            symlink_target: None,
+        };
+        let updated_file_wrapper = FileWrapper {
+            path: PathBuf::from("name.docx"),
+            last_accessed: SystemTime::UNIX_EPOCH,
+            ..original_file_wrapper.clone()
+        };
+        assert!(dbcontext.store_file(&original_file_wrapper).is_ok());
+        let result = dbcontext.store_file(&updated_file_wrapper);
+        assert!(result.is_ok());
+
+        let result = dbcontext.get_file(&original_file_wrapper.id);
+        assert_eq!(result.unwrap().unwrap(), updated_file_wrapper);
+    }
+
+    #[test]
+    #[serial]
+    fn store_file_should_update_stored_file_details_if_version_is_different() {
+        delete_db();
+        let dbcontext_connection = get_connection();
+        let dbcontext = DbContext::new(dbcontext_connection);
+        let connection = get_connection();
+        let init_result = dbcontext.init();
+        assert!(init_result.is_ok());
+        let original_file_wrapper = FileWrapper {
+            id: "id".to_string(),
+            name: "name".to_string(),
+            mime_type: "mime_type".to_string(),
+            path: PathBuf::from("dbcontext.rs"),
+            directory: false,
+            web_view_link: Some("web_view_link".to_string()),
+            owned_by_me: true,
+            last_modified: DateTime::from(Utc::now()),
+            last_accessed: SystemTime::from(Utc::now()),
+            trashed: false,
+            version: Some("\"etag1\"".to_string()),
+            parents: Vec::new(),
+            can_edit: true,
+            shortcut_target_id: None,
+            permissions: Permissions::default(),
+            symlink_target: None,
+        };
+        let updated_file_wrapper = FileWrapper {
+            last_accessed: SystemTime::UNIX_EPOCH,
+            version: Some("\"etag2\"".to_string()),
+            parents: Vec::new(),
+            ..original_file_wrapper.clone()
+        };
+        insert_file_wrapper(&connection, &original_file_wrapper);
+        let result = dbcontext.store_file(&updated_file_wrapper);
+        assert!(result.is_ok());
+
+        let result = dbcontext.get_file(&original_file_wrapper.id);
+        assert_eq!(result.unwrap().unwrap(), updated_file_wrapper);
+    }
+
+    #[test]
+    #[serial]
+    fn init_should_add_version_column_to_existing_table() {
+        delete_db();
+        let dbcontext_connection = get_connection();
+        let dbcontext = DbContext::new(dbcontext_connection);
+        let connection = get_connection();
+        let create_result = connection.execute(
+            "CREATE TABLE file (
+                id TEXT PRIMARY KEY,
+                name TEXT NOT NULL,
+                mime_type TEXT NOT NULL,
+                path TEXT NOT NULL,
+                directory INTEGER NOT NULL,
+                web_view_link TEXT,
+                owned_by_me INTEGER NOT NULL,
+                last_modified TEXT NOT NULL,
+                last_accessed TEXT NOT NULL,
+                trashed INTEGER NOT NULL
+            )",
+            [],
+        );
+        assert!(create_result.is_ok());
+        let result = dbcontext.init();
+        assert!(result.is_ok());
+
+        let count: Result<i32> = connection.query_row(
+            "SELECT COUNT(*) FROM pragma_table_info('file') WHERE name = 'version'",
+            [],
+            |row| row.get(0),
+        );
+        assert_eq!(count.unwrap(), 1);
+    }
+
+    #[test]
+    #[serial]
+    fn get_file_by_path_should_get_stored_file() {
+        delete_db();
+        let dbcontext_connection = get_connection();
+        let dbcontext = DbContext::new(dbcontext_connection);
+        let connection = get_connection();
+        let init_result = dbcontext.init();
+        assert!(init_result.is_ok());
+        let stored_file_wrapper = FileWrapper {
+            id: "id".to_string(),
+            name: "name".to_string(),
+            mime_type: "mime_type".to_string(),
+            path: PathBuf::from("src/dbcontext.rs"),
+            directory: false,
+            web_view_link: Some("web_view_link".to_string()),
+            owned_by_me: true,
+            last_modified: DateTime::from(Utc::now()),
+            last_accessed: SystemTime::from(Utc::now()),
+            trashed: false,
+            version: None,
+            parents: Vec::new(),
+            can_edit: true,
+            shortcut_target_id: None,
+            permissions: Permissions::default(),
+            symlink_target: None,
+        };
+        insert_file_wrapper(&connection, &stored_file_wrapper);
+        let result = dbcontext.get_file_by_path(Path::new("src/dbcontext.rs"));
+        assert_eq!(result.unwrap().unwrap(), stored_file_wrapper);
+        let missing = dbcontext.get_file_by_path(Path::new("src/drive.rs"));
+        assert_eq!(missing.unwrap(), None);
+    }
+
+    #[test]
+    #[serial]
+    fn store_file_should_store_parents_even_if_file_is_unchanged() {
+        delete_db();
+        let dbcontext_connection = get_connection();
+        let dbcontext = DbContext::new(dbcontext_connection);
+        let init_result = dbcontext.init();
+        assert!(init_result.is_ok());
+        let original_file_wrapper = FileWrapper {
+            id: "id".to_string(),
+            name: "name".to_string(),
+            mime_type: "mime_type".to_string(),
+            path: PathBuf::from("path"),
+            directory: false,
+            web_view_link: None,
+            owned_by_me: true,
+            last_modified: DateTime::from(Utc::now()),
+            last_accessed: SystemTime::UNIX_EPOCH,
+            trashed: false,
+            version: None,
+            parents: vec!["parent_1".to_string(), "parent_2".to_string()],
+            can_edit: true,
+            shortcut_target_id: None,
+            permissions: Permissions::default(),
+            symlink_target: None,
+        };
+        let result = dbcontext.store_file(&original_file_wrapper);
+        assert!(result.is_ok());
+        assert_eq!(
+            dbcontext
+                .get_file(&original_file_wrapper.id)
+                .unwrap()
+                .unwrap(),
+            original_file_wrapper
+        );
+
+        let moved_file_wrapper = FileWrapper {
+            parents: vec!["parent_2".to_string()],
+            ..original_file_wrapper.clone()
+        };
+        let result = dbcontext.store_file(&moved_file_wrapper);
+        assert!(result.is_ok());
+        assert_eq!(
+            dbcontext.get_parent_ids(&original_file_wrapper.id),
+            Ok(vec!["parent_2".to_string()])
+        );
+    }
+
+    #[test]
+    #[serial]
+    fn get_children_should_get_files_with_parent() {
+        delete_db();
+        let dbcontext_connection = get_connection();
+        let dbcontext = DbContext::new(dbcontext_connection);
+        let init_result = dbcontext.init();
+        assert!(init_result.is_ok());
+        let child_file_wrapper = FileWrapper {
+            id: "child".to_string(),
+            name: "child".to_string(),
+            mime_type: "mime_type".to_string(),
+            path: PathBuf::from("folder/child"),
+            directory: false,
+            web_view_link: None,
+            owned_by_me: true,
+            last_modified: DateTime::from(Utc::now()),
+            last_accessed: SystemTime::UNIX_EPOCH,
+            trashed: false,
+            version: None,
+            parents: vec!["folder".to_string()],
+            can_edit: true,
+            shortcut_target_id: None,
+            permissions: Permissions::default(),
+            symlink_target: None,
+        };
+        let other_file_wrapper = FileWrapper {
+            id: "other".to_string(),
+            name: "other".to_string(),
+            path: PathBuf::from("other"),
+            parents: vec!["root".to_string()],
+            ..child_file_wrapper.clone()
+        };
+        assert!(dbcontext.store_file(&child_file_wrapper).is_ok());
+        assert!(dbcontext.store_file(&other_file_wrapper).is_ok());
+
+        let result = dbcontext.get_children("folder");
+        assert_eq!(result, Ok(vec![child_file_wrapper]));
+        let missing = dbcontext.get_children("missing");
+        assert_eq!(missing, Ok(vec![]));
+    }
+
+    #[test]
+    #[serial]
+    fn delete_file_should_delete_only_that_file_and_its_rows() {
+        delete_db();
+        let dbcontext_connection = get_connection();
+        let dbcontext = DbContext::new(dbcontext_connection);
+        let init_result = dbcontext.init();
+        assert!(init_result.is_ok());
+        let file_wrapper = FileWrapper {
+            id: "id".to_string(),
+            name: "name".to_string(),
+            mime_type: "mime_type".to_string(),
+            path: PathBuf::from("folder/name"),
+            directory: false,
+            web_view_link: None,
+            owned_by_me: true,
+            last_modified: DateTime::from(Utc::now()),
+            last_accessed: SystemTime::UNIX_EPOCH,
+            trashed: false,
+            version: None,
+            parents: vec!["parent_id".to_string()],
+            can_edit: true,
+            shortcut_target_id: None,
+            permissions: Permissions::default(),
+            symlink_target: None,
+        };
+        let other_file_wrapper = FileWrapper {
+            id: "other".to_string(),
+            path: PathBuf::from("folder/other"),
+            ..file_wrapper.clone()
+        };
+        assert!(dbcontext.store_file(&file_wrapper).is_ok());
+        assert!(dbcontext.store_file(&other_file_wrapper).is_ok());
+        for id in ["id", "other"] {
+            assert!(dbcontext.store_name_suffix(id, 1).is_ok());
+            assert!(dbcontext
+                .store_remote_name(id, &PathBuf::from(id), "remote:name")
+                .is_ok());
+        }
+
+        assert!(dbcontext.delete_file("id").is_ok());
+        assert_eq!(dbcontext.get_all_files(), Ok(vec![other_file_wrapper]));
+        assert_eq!(dbcontext.get_parent_ids("id"), Ok(Vec::new()));
+        assert_eq!(
+            dbcontext.get_name_suffixes(),
+            Ok(HashMap::from([("other".to_string(), 1)]))
+        );
+        assert_eq!(
+            dbcontext.get_remote_names(),
+            Ok(HashMap::from([(
+                "other".to_string(),
+                (PathBuf::from("other"), "remote:name".to_string())
+            )]))
+        );
+    }
+
+    #[test]
+    #[serial]
+    fn clear_files_should_delete_files_and_parents() {
+        delete_db();
+        let dbcontext_connection = get_connection();
+        let dbcontext = DbContext::new(dbcontext_connection);
+        let init_result = dbcontext.init();
+        assert!(init_result.is_ok());
+        let file_wrapper = FileWrapper {
+            id: "id".to_string(),
+            name: "name".to_string(),
+            mime_type: "mime_type".to_string(),
+            path: PathBuf::from("folder/name"),
+            directory: false,
+            web_view_link: None,
+            owned_by_me: true,
+            last_modified: DateTime::from(Utc::now()),
+            last_accessed: SystemTime::UNIX_EPOCH,
+            trashed: false,
+            version: None,
+            parents: vec!["parent_id".to_string()],
+            can_edit: true,
+            shortcut_target_id: None,
+            permissions: Permissions::default(),
+            symlink_target: None,
+        };
+        assert!(dbcontext.store_file(&file_wrapper).is_ok());
+
+        assert!(dbcontext.clear_files().is_ok());
+        assert_eq!(dbcontext.get_all_files(), Ok(Vec::new()));
+        assert_eq!(dbcontext.get_children("parent_id"), Ok(Vec::new()));
+    }
+
+    #[test]
+    #[serial]
+    fn store_import_should_store_import_path() {
+        delete_db();
+        let dbcontext_connection = get_connection();
+        let dbcontext = DbContext::new(dbcontext_connection);
+        let init_result = dbcontext.init();
+        assert!(init_result.is_ok());
+        let result = dbcontext.store_import("id", Path::new("docs/report.docx"));
+        assert!(result.is_ok());
+
+        let result = dbcontext.get_import_path("id");
+        assert_eq!(result, Ok(Some(PathBuf::from("docs/report.docx"))));
+        let missing = dbcontext.get_import_path("missing");
+        assert_eq!(missing, Ok(None));
+        let imports = dbcontext.get_imports().unwrap();
+        assert_eq!(imports.get("id"), Some(&PathBuf::from("docs/report.docx")));
+    }
+
+    #[test]
+    #[serial]
+    fn store_name_suffix_should_replace_stored_suffix() {
+        delete_db();
+        let dbcontext_connection = get_connection();
+        let dbcontext = DbContext::new(dbcontext_connection);
+        let init_result = dbcontext.init();
+        assert!(init_result.is_ok());
+        assert!(dbcontext.store_name_suffix("id_1", 0).is_ok());
+        assert!(dbcontext.store_name_suffix("id_2", 1).is_ok());
+        assert!(dbcontext.store_name_suffix("id_2", 2).is_ok());
+
+        let suffixes = dbcontext.get_name_suffixes().unwrap();
+        assert_eq!(suffixes.len(), 2);
+        assert_eq!(suffixes.get("id_1"), Some(&0));
+        assert_eq!(suffixes.get("id_2"), Some(&2));
+    }
+
+    #[test]
+    #[serial]
+    fn store_remote_name_should_map_local_path_to_drive_name() {
+        delete_db();
+        let dbcontext_connection = get_connection();
+        let dbcontext = DbContext::new(dbcontext_connection);
+        let init_result = dbcontext.init();
+        assert!(init_result.is_ok());
+        let result = dbcontext.store_remote_name("id", Path::new("docs/a_b.txt"), "a:b.txt");
+        assert!(result.is_ok());
+
+        let result = dbcontext.get_remote_name(Path::new("docs/a_b.txt"));
+        assert_eq!(result, Ok(Some("a:b.txt".to_string())));
+        let remote_names = dbcontext.get_remote_names().unwrap();
+        assert_eq!(
+            remote_names.get("id"),
+            Some(&(PathBuf::from("docs/a_b.txt"), "a:b.txt".to_string()))
+        );
+        assert!(dbcontext.delete_remote_name("id").is_ok());
+        let result = dbcontext.get_remote_name(Path::new("docs/a_b.txt"));
+        assert_eq!(result, Ok(None));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    #[serial]
+    fn store_import_should_return_path_error_for_non_utf8_path() {
+        use std::ffi::OsStr;
+        use std::os::unix::ffi::OsStrExt;
+
+        delete_db();
+        let dbcontext_connection = get_connection();
+        let dbcontext = DbContext::new(dbcontext_connection);
+        let init_result = dbcontext.init();
+        assert!(init_result.is_ok());
+        let path = Path::new(OsStr::from_bytes(b"docs/\xff.txt"));
+
+        let result = dbcontext
+            .store_import("id", path)
+            .map_err(RdriveError::from);
+
+        assert!(matches!(result, Err(RdriveError::Path(error_path, _)) if error_path == path));
+    }
+
     #[test]
     #[serial]
     fn get_file_should_return_none_if_no_stored_file() {
@@ -362,7 +1062,7 @@ mod tests {
         let init_result = dbcontext.init();
         assert!(init_result.is_ok());
         let result = dbcontext.get_file(&"id".to_string());
-        assert_eq!(result, None);
+        assert_eq!(result, Ok(None));
     }
 
     #[test]
@@ -385,10 +1085,16 @@ mod tests {
             last_modified: DateTime::from(Utc::now()),
             last_accessed: SystemTime::from(Utc::now()),
             trashed: false,
+            version: None,
+            parents: Vec::new(),
+            can_edit: true,
+            shortcut_target_id: None,
+            permissions: Permissions::default(),
+            symlink_target: None,
         };
         insert_file_wrapper(&connection, &stored_file_wrapper);
         let result = dbcontext.get_file(&stored_file_wrapper.id);
-        assert_eq!(result.unwrap(), stored_file_wrapper);
+        assert_eq!(result.unwrap().unwrap(), stored_file_wrapper);
     }
 
     #[test]
@@ -423,6 +1129,12 @@ mod tests {
             last_modified: DateTime::from(Utc::now()),
             last_accessed: SystemTime::from(Utc::now()),
             trashed: false,
+            version: None,
+            parents: Vec::new(),
+            can_edit: true,
+            shortcut_target_id: None,
+            permissions: Permissions::default(),
+            symlink_target: None,
         };
         let stored_file_wrapper_2 = FileWrapper {
             id: "id2".to_string(),
@@ -437,11 +1149,17 @@ mod tests {
                 Utc::now().with_minute(Utc::now().minute() + 1).unwrap(),
             ),
             trashed: true,
+            version: Some("version".to_string()),
+            parents: Vec::new(),
+            can_edit: true,
+            shortcut_target_id: None,
+            permissions: Permissions::default(),
+            symlink_target: None,
         };
         insert_file_wrapper(&connection, &stored_file_wrapper_1);
         insert_file_wrapper(&connection, &stored_file_wrapper_2);
         let result = dbcontext.get_file(&stored_file_wrapper_2.id);
-        assert_eq!(result.unwrap(), stored_file_wrapper_2);
+        assert_eq!(result.unwrap().unwrap(), stored_file_wrapper_2);
     }
 
     #[test]
@@ -464,6 +1182,12 @@ mod tests {
             last_modified: DateTime::from(Utc::now()),
             last_accessed: SystemTime::from(Utc::now()),
             trashed: false,
+            version: None,
+            parents: Vec::new(),
+            can_edit: true,
+            shortcut_target_id: None,
+            permissions: Permissions::default(),
+            symlink_target: None,
         };
         insert_file_wrapper(&connection, &file_wrapper);
         let time = SystemTime::now();
@@ -503,6 +1227,12 @@ mod tests {
                 last_modified: DateTime::from(Utc::now()),
                 last_accessed: SystemTime::from(Utc::now()),
                 trashed: false,
+                version: None,
+                parents: Vec::new(),
+                can_edit: true,
+                shortcut_target_id: None,
+                permissions: Permissions::default(),
+                symlink_target: None,
             };
             dbcontext.store_file(&file_wrapper)?;
             Err(Error::SqliteFailure(
@@ -547,6 +1277,12 @@ mod tests {
             last_modified: DateTime::from(Utc::now()),
             last_accessed: SystemTime::from(Utc::now()),
             trashed: false,
+            version: None,
+            parents: Vec::new(),
+            can_edit: true,
+            shortcut_target_id: None,
+            permissions: Permissions::default(),
+            symlink_target: None,
         };
         let result = dbcontext.transaction(|| -> Result<(), Error> {
             dbcontext.store_file(&expected_file_wrapper)?;
@@ -574,6 +1310,16 @@ mod tests {
                         DateTime::parse_from_rfc3339(&last_accessed).unwrap(),
                     ),
                     trashed: row.get(9).unwrap(),
+                    version: row.get(10).unwrap(),
+                    parents: Vec::new(),
+                    can_edit: row.get(11).unwrap(),
+                    shortcut_target_id: row.get(12).unwrap(),
+                    permissions: Permissions {
+                        mode: row.get(13).unwrap(),
+                        owner: row.get(14).unwrap(),
+                        group: row.get(15).unwrap(),
+                    },
+                    symlink_target: row.get(16).unwrap(),
                 })
             },
         );
@@ -582,7 +1328,7 @@ mod tests {
 
     fn insert_file_wrapper(connection: &Connection, file_wrapper: &FileWrapper) {
         let last_accessed_converted: DateTime<Local> = DateTime::from(file_wrapper.last_accessed);
-        let result = connection.execute("INSERT INTO file (id, name, mime_type, path, directory, web_view_link, owned_by_me, last_modified, last_accessed, trashed) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)", [
+        let result = connection.execute("INSERT INTO file (id, name, mime_type, path, directory, web_view_link, owned_by_me, last_modified, last_accessed, trashed, version, can_edit, shortcut_target_id, mode, owner, group_name, symlink_target) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)", rusqlite::params![
             &file_wrapper.id,
             &file_wrapper.name,
             &file_wrapper.mime_type,
@@ -592,23 +1338,34 @@ mod tests {
             &(file_wrapper.owned_by_me as i32).to_string(),
             &file_wrapper.last_modified.to_rfc3339(),
             &last_accessed_converted.to_rfc3339(),
-            &(file_wrapper.trashed as i32).to_string()
+            &(file_wrapper.trashed as i32).to_string(),
+            &file_wrapper.version,
+            &file_wrapper.can_edit,
+            &file_wrapper.shortcut_target_id,
+            &file_wrapper.permissions.mode,
+            &file_wrapper.permissions.owner,
+            &file_wrapper.permissions.group,
+            &file_wrapper.symlink_target
         ]);
         assert!(result.is_ok());
     }
 
-    const DB_PATH: &str = "test.db";
+    /// Tests open more than one connection to the same database, so it is kept in a file in the
+    /// temp directory rather than in memory.
+    fn db_path() -> PathBuf {
+        std::env::temp_dir().join("rdrive-dbcontext-test.db")
+    }
 
     fn get_connection() -> Connection {
-        Connection::open("test.db").unwrap()
+        Connection::open(db_path()).unwrap()
     }
 
     fn delete_db() {
-        let removed = remove_file(DB_PATH);
+        let removed = remove_file(db_path());
         if removed.is_err() {
             println!(
                 "Failed to remove {}. If any tests failed, this could be why.",
-                DB_PATH
+                db_path().display()
             );
         }
     }
@@ -1,104 +1,915 @@
-use std::fs::{create_dir_all, read_dir};
-use std::io::{BufReader, BufWriter, Write};
+use std::cell::{OnceCell, RefCell};
+use std::collections::{HashMap, HashSet};
+use std::fs::{create_dir_all, read_dir, DirEntry};
+use std::io::Write;
 use std::path::PathBuf;
-use std::time::SystemTime;
-use std::{borrow::Borrow, collections::HashMap, env, fs, path::Path};
+use std::rc::Rc;
+use std::time::{Duration, SystemTime};
+use std::{fs, path::Path};
 
-use async_recursion::async_recursion;
 use chrono::{DateTime, FixedOffset, Local};
-use drive3::api::{File, Scope};
-use drive3::common::{to_bytes, Body};
-use drive3::hyper::Response;
-use drive3::hyper_rustls::HttpsConnector;
-use drive3::hyper_util::client::legacy::connect::HttpConnector;
-use drive3::DriveHub;
-use glob::Pattern;
-use log::{debug, error};
-use rusqlite::Connection;
-use serde::{Deserialize, Serialize};
+use log::{debug, error, warn};
+use tokio::time::sleep;
 
+use crate::check::{self, Issue};
+use crate::checksum;
+use crate::config::{Config, SymlinkPolicy, SyncMode};
+use crate::conflict;
 use crate::dbcontext::DbContext;
+use crate::error::{RdriveError, Result};
+use crate::events::{SyncEvent, SyncListener};
+use crate::export::{self, ExportFormat};
+use crate::link;
+use crate::naming;
+use crate::permissions::{self, Permissions};
+use crate::remote::{Remote, RemoteFile, Revision};
+use crate::trash::{self, TrashEntry};
+use crate::xattr;
 
 pub struct Drive {
-    hub: DriveHub<HttpsConnector<HttpConnector>>,
+    remote: Rc<dyn Remote>,
     context: DbContext,
     config: Config,
+    listeners: Vec<SyncListener>,
+    case_insensitive: OnceCell<bool>,
+    /// The ID of the remote folder synced to the root directory, once it has been found.
+    remote_folder_id: RefCell<Option<String>>,
 }
 
 impl Drive {
-    pub fn new(hub: DriveHub<HttpsConnector<HttpConnector>>, connection: Connection) -> Drive {
+    /// Engines for several sync pairs can share one remote, by passing each a clone of the same
+    /// `Rc`, so they authenticate once and can be run together by `run_pairs`.
+    pub fn new(remote: impl Into<Rc<dyn Remote>>, context: DbContext, config: Config) -> Drive {
         Drive {
-            hub,
-            context: DbContext::new(connection),
-            config: Drive::get_config(),
-        }
-    }
-
-    pub async fn init(&self) {
-        self.context.init().unwrap();
-        self.store_fetched_files().await.unwrap();
-    }
-
-    #[async_recursion(?Send)]
-    async fn fetch_files(&self, page_token: Option<String>) -> Vec<File> {
-        let fields = "nextPageToken, files(id, kind, name, description, kind, mimeType, parents, ownedByMe, webContentLink, webViewLink, modifiedTime, trashed)";
-        let mut file_list_call = self
-            .hub
-            .files()
-            .list()
-            .add_scope(Scope::Full)
-            .param("fields", fields);
-        if let Some(token) = page_token {
-            file_list_call = file_list_call.page_token(token.as_str())
-        }
-        let hub_result = file_list_call.doit().await;
-        match hub_result {
-            Ok(x) => {
-                let mut files = x.1.files.unwrap();
-                if x.1.next_page_token.is_some() {
-                    for file in self.fetch_files(x.1.next_page_token.to_owned()).await {
-                        files.push(file);
+            remote: remote.into(),
+            context,
+            config,
+            listeners: Vec::new(),
+            case_insensitive: OnceCell::new(),
+            remote_folder_id: RefCell::new(None),
+        }
+    }
+
+    pub fn root_dir(&self) -> &Path {
+        &self.config.root_dir
+    }
+
+    pub fn subscribe(&mut self, listener: impl Fn(&SyncEvent) + 'static) {
+        self.listeners.push(Box::new(listener));
+    }
+
+    fn emit(&self, event: SyncEvent) {
+        for listener in &self.listeners {
+            listener(&event);
+        }
+    }
+
+    pub async fn init(&self) -> Result<()> {
+        self.context.init()?;
+        self.store_fetched_files().await
+    }
+
+    /// Rebuilds the index of synced files from the remote and the extended attributes written to
+    /// local files, for when the database has been lost. Local files that are still at the
+    /// version they were synced at are kept rather than downloaded again, and files moved while
+    /// rdrive was stopped are moved on the remote to match. Returns how many local files were
+    /// matched to remote files.
+    pub async fn rebuild(&self) -> Result<usize> {
+        self.context.init()?;
+        self.context.clear_files()?;
+        self.store_fetched_files().await?;
+        let mut tagged_files = Vec::new();
+        for local_file in self.get_local_files()? {
+            if local_file.directory || local_file.symlink_target.is_some() {
+                continue;
+            }
+            match xattr::get(&local_file.path, xattr::ID_ATTRIBUTE) {
+                Ok(Some(id)) => tagged_files.push((local_file, id)),
+                Ok(None) => {}
+                Err(error) => self.rebuild_failed(&local_file, error.into()),
+            }
+        }
+        let mut id_counts: HashMap<&str, usize> = HashMap::new();
+        for (_, id) in &tagged_files {
+            *id_counts.entry(id.as_str()).or_default() += 1;
+        }
+        let mut matched = 0;
+        for (local_file, id) in &tagged_files {
+            let is_duplicate = id_counts.get(id.as_str()).is_some_and(|count| *count > 1);
+            match self.rebuild_file(local_file, id, is_duplicate).await {
+                Ok(true) => matched += 1,
+                Ok(false) => {}
+                Err(error) => self.rebuild_failed(local_file, error),
+            }
+        }
+        Ok(matched)
+    }
+
+    fn rebuild_failed(&self, local_file: &FileWrapper, error: RdriveError) {
+        error!("Unable to rebuild {}. {}", local_file.path.display(), error);
+        self.emit(SyncEvent::Failed {
+            path: local_file.path.clone(),
+            error: error.to_string(),
+        });
+    }
+
+    /// Matches a local file tagged with the remote file `id`. Copies made with their extended
+    /// attributes, such as by `cp -a`, carry the same ID, so when several local files do only
+    /// the one at the synced path is matched and the others are left to be uploaded as new files.
+    async fn rebuild_file(
+        &self,
+        local_file: &FileWrapper,
+        id: &String,
+        is_duplicate: bool,
+    ) -> Result<bool> {
+        let mut file_wrapper = match self.context.get_file(id)? {
+            Some(file_wrapper) if !file_wrapper.trashed => file_wrapper,
+            _ => {
+                debug!(
+                    "{} was synced with {}, which is no longer on the remote",
+                    local_file.path.display(),
+                    id
+                );
+                return Ok(false);
+            }
+        };
+        if file_wrapper.path != local_file.path {
+            if is_duplicate {
+                warn!(
+                    "{} has the same ID as another local file, so won't be matched to {}",
+                    local_file.path.display(),
+                    id
+                );
+                return Ok(false);
+            }
+            if link::is_google_native(&file_wrapper.mime_type)
+                || self.context.get_import_path(id)?.is_some()
+            {
+                return Ok(false);
+            }
+            debug!(
+                "{} was moved to {} while rdrive was stopped",
+                file_wrapper.path.display(),
+                local_file.path.display()
+            );
+            let parent_id = self.get_parent_id(local_file)?;
+            let remote_file = self
+                .remote
+                .move_file(&file_wrapper, parent_id.as_deref(), &local_file.name)
+                .await?;
+            file_wrapper = Drive::convert_to_file_wrapper(&remote_file, &local_file.path);
+            self.context.store_file(&file_wrapper)?;
+        }
+        let version = xattr::get(&local_file.path, xattr::VERSION_ATTRIBUTE)?;
+        if version == file_wrapper.version {
+            self.context
+                .update_last_accessed(&file_wrapper.id, &file_wrapper.last_modified.into())?;
+        }
+        Ok(true)
+    }
+
+    /// Rebuilds the index of synced files by matching the local tree against the remote by path,
+    /// size and MD5 checksum, without transferring any content. Local files that match are
+    /// treated as in sync, while files that differ keep whichever copy was modified last on the
+    /// next sync. Returns how many local files were matched to remote files.
+    pub async fn reindex(&self) -> Result<usize> {
+        self.context.init()?;
+        self.context.clear_files()?;
+        let remote_files = self.list_remote_files().await?;
+        self.store_remote_files(&remote_files)?;
+        let mut matched = 0;
+        for remote_file in &remote_files {
+            let file_wrapper = match self.context.get_file(&remote_file.id)? {
+                Some(file_wrapper) if !file_wrapper.directory && !file_wrapper.trashed => {
+                    file_wrapper
+                }
+                _ => continue,
+            };
+            match self.reindex_file(remote_file, &file_wrapper) {
+                Ok(true) => matched += 1,
+                Ok(false) => {}
+                Err(error) => {
+                    error!(
+                        "Unable to reindex {}. {}",
+                        file_wrapper.path.display(),
+                        error
+                    );
+                    self.emit(SyncEvent::Failed {
+                        path: file_wrapper.path.clone(),
+                        error: error.to_string(),
+                    });
+                }
+            }
+        }
+        Ok(matched)
+    }
+
+    fn reindex_file(&self, remote_file: &RemoteFile, file_wrapper: &FileWrapper) -> Result<bool> {
+        let metadata = match fs::symlink_metadata(&file_wrapper.path) {
+            Ok(metadata) => metadata,
+            Err(_) => return Ok(false),
+        };
+        let is_symlink = metadata.file_type().is_symlink();
+        let matches = if file_wrapper.shortcut_target_id.is_some()
+            || file_wrapper.symlink_target.is_some()
+        {
+            is_symlink
+        } else if is_symlink || metadata.is_dir() {
+            false
+        } else if link::is_google_native(&file_wrapper.mime_type) {
+            // Links and exports are generated locally, so there's no content to compare.
+            true
+        } else if remote_file.size.is_none() && remote_file.md5_checksum.is_none() {
+            false
+        } else {
+            remote_file.size.is_none_or(|size| size == metadata.len())
+                && match &remote_file.md5_checksum {
+                    Some(md5_checksum) => checksum::md5_file(&file_wrapper.path)? == *md5_checksum,
+                    None => true,
+                }
+        };
+        let baseline = if matches {
+            metadata.modified()?
+        } else {
+            warn!(
+                "{} differs from the remote, the copy modified last will be kept",
+                file_wrapper.path.display()
+            );
+            file_wrapper.last_modified.into()
+        };
+        self.context
+            .update_last_accessed(&file_wrapper.id, &baseline)?;
+        Ok(matches)
+    }
+
+    pub async fn run(&self, interval: Duration) {
+        Drive::run_pairs(self.remote.as_ref(), std::slice::from_ref(self), interval).await
+    }
+
+    /// Syncs several pairs that share `remote`, listing the remote once each cycle and scoping the
+    /// listing to each pair's remote folder, rather than listing the whole remote for every pair.
+    pub async fn run_pairs(remote: &dyn Remote, drives: &[Drive], interval: Duration) {
+        loop {
+            match remote.list_files().await {
+                Ok(remote_files) => {
+                    for drive in drives {
+                        if let Err(error) = drive.sync_listed(remote_files.clone()).await {
+                            error!(
+                                "Sync cycle of {} failed, retrying in {:?}. {}",
+                                drive.root_dir().display(),
+                                interval,
+                                error
+                            );
+                        }
+                    }
+                }
+                Err(error) => error!(
+                    "Listing the remote failed, retrying in {:?}. {}",
+                    interval, error
+                ),
+            }
+            sleep(interval).await;
+        }
+    }
+
+    pub async fn sync(&self) -> Result<()> {
+        let remote_files = self.remote.list_files().await?;
+        self.sync_listed(remote_files).await
+    }
+
+    /// Syncs using a listing of every remote file that has already been fetched.
+    async fn sync_listed(&self, remote_files: Vec<RemoteFile>) -> Result<()> {
+        self.check_root_dir()?;
+        let remote_files = self.scope_to_remote_folder(remote_files)?;
+        self.store_remote_files(&remote_files)?;
+        let existing_file_wrappers = self.get_all_files(!self.config.shared_with_me)?;
+        debug!("Retrieved {} files", existing_file_wrappers.len());
+        for file_wrapper in &existing_file_wrappers {
+            self.handle_existing_file(file_wrapper).await
+        }
+        let local_files: Vec<FileWrapper> = self.get_local_files()?;
+        for file_wrapper in &local_files {
+            if existing_file_wrappers
+                .iter()
+                .any(|f| f.path == file_wrapper.path)
+            {
+                debug!(
+                    "Not handling {} as a local file as it's already been handled",
+                    file_wrapper.path.display()
+                )
+            } else {
+                if file_wrapper.directory {
+                    debug!("Can't currently handle new directories");
+                    continue;
+                }
+                if link::is_link_file(&file_wrapper.path) {
+                    debug!(
+                        "Not uploading {} as it's a link to a Google file",
+                        file_wrapper.path.display()
+                    );
+                    continue;
+                }
+                if self.config.mode == SyncMode::DownloadOnly {
+                    self.revert_new_file(file_wrapper);
+                    continue;
+                }
+                debug!(
+                    "Upload {} to the remote for the first time",
+                    file_wrapper.path.display()
+                );
+                if let Err(result) = self.upload_new_file(file_wrapper).await {
+                    error!(
+                        "Error occurred whilst uploading {} to the remote for the first time. {}",
+                        file_wrapper.path.display(),
+                        result
+                    );
+                    self.emit(SyncEvent::Failed {
+                        path: file_wrapper.path.clone(),
+                        error: result.to_string(),
+                    });
+                }
+            }
+        }
+        self.purge_trash();
+        self.emit(SyncEvent::CycleCompleted);
+        Ok(())
+    }
+
+    /// New local files aren't uploaded when only downloads are allowed, so are moved to the trash
+    /// to keep the local directory a mirror of the remote.
+    fn revert_new_file(&self, file_wrapper: &FileWrapper) {
+        if !self.config.trash {
+            debug!(
+                "Not uploading {} as only downloads are allowed",
+                file_wrapper.path.display()
+            );
+            return;
+        }
+        debug!(
+            "Moving {} to the trash as only downloads are allowed",
+            file_wrapper.path.display()
+        );
+        if let Err(error) = trash::move_to_trash(&self.config.root_dir, &file_wrapper.path) {
+            error!(
+                "Unable to move {} to the trash. {}",
+                file_wrapper.path.display(),
+                error
+            );
+        }
+    }
+
+    fn purge_trash(&self) {
+        let purge_result = trash::purge(
+            &self.config.root_dir,
+            self.config.trash_max_age,
+            self.config.trash_max_size,
+            SystemTime::now(),
+        );
+        match purge_result {
+            Ok(0) => {}
+            Ok(purged) => debug!("Deleted {} files from the trash", purged),
+            Err(error) => error!("Unable to empty the trash. {}", error),
+        }
+    }
+
+    /// The files in the trash, oldest first.
+    pub fn get_trash(&self) -> Result<Vec<TrashEntry>> {
+        Ok(trash::list(&self.config.root_dir)?)
+    }
+
+    /// Puts a file from the trash back where it was. The restored file is marked as modified now,
+    /// so the next sync uploads it rather than replacing it with the remote copy again.
+    pub fn restore(&self, entry: &TrashEntry) -> Result<()> {
+        trash::restore(&self.config.root_dir, entry)?;
+        if entry.path.is_file() {
+            Drive::set_read_only(&entry.path, false)?;
+            Drive::set_modified(&entry.path, SystemTime::now())?;
+        }
+        Ok(())
+    }
+
+    async fn upload_new_file(&self, file_wrapper: &FileWrapper) -> Result<()> {
+        match self.get_import_mime_type(file_wrapper) {
+            Some(mime_type) => self.import_file(file_wrapper, mime_type).await,
+            None => self.upload_file(file_wrapper).await,
+        }
+    }
+
+    /// Compares the index with the local tree and the remote, returning every way they have
+    /// drifted apart. With `repair`, each issue is corrected: missing and mismatched files are
+    /// downloaded again, files moved on the remote are moved locally to match, rows for files
+    /// that are no longer on the remote are forgotten and unsynced local files are uploaded.
+    pub async fn check(&self, repair: bool) -> Result<Vec<Issue>> {
+        self.context.init()?;
+        let remote_files = self.list_remote_files().await?;
+        let stored_files = self.context.get_all_files()?;
+        let local_files = self.get_local_files()?;
+        let issues = check::find_issues(&stored_files, &remote_files, &local_files)?;
+        if !repair || issues.is_empty() {
+            return Ok(issues);
+        }
+        self.store_remote_files(&remote_files)?;
+        for issue in &issues {
+            if let Err(error) = self.repair(issue, &stored_files, &local_files).await {
+                error!("Unable to repair {}. {}", issue, error);
+            }
+        }
+        Ok(issues)
+    }
+
+    async fn repair(
+        &self,
+        issue: &Issue,
+        stored_files: &[FileWrapper],
+        local_files: &[FileWrapper],
+    ) -> Result<()> {
+        match issue {
+            Issue::MissingLocally { id, .. } | Issue::ChecksumMismatch { id, .. } => {
+                if self.config.mode == SyncMode::UploadOnly {
+                    return Ok(());
+                }
+                match self.context.get_file(id)? {
+                    Some(file_wrapper) => self.create_file(&file_wrapper).await,
+                    None => Ok(()),
+                }
+            }
+            Issue::OrphanRow { id, .. } => Ok(self.context.delete_file(id)?),
+            Issue::StalePath { id, path } => {
+                let file_wrapper = match self.context.get_file(id)? {
+                    Some(file_wrapper) if file_wrapper.path != *path => file_wrapper,
+                    _ => return Ok(()),
+                };
+                if file_wrapper.path.exists() {
+                    return Ok(());
+                }
+                debug!(
+                    "Moving {} to {} to match the remote",
+                    path.display(),
+                    file_wrapper.path.display()
+                );
+                if let Some(parent) = file_wrapper.path.parent() {
+                    create_dir_all(parent)?;
+                }
+                fs::rename(path, &file_wrapper.path)?;
+                // The content is only downloaded again if it also changed on the remote.
+                if let Some(stored_file) = stored_files.iter().find(|file| file.id == *id) {
+                    if stored_file.version == file_wrapper.version {
+                        self.context
+                            .update_last_accessed(id, &stored_file.last_accessed)?;
                     }
                 }
-                files
+                Ok(())
+            }
+            Issue::Unsynced { .. } if self.config.mode == SyncMode::DownloadOnly => Ok(()),
+            Issue::Unsynced { path } => match local_files.iter().find(|file| file.path == *path) {
+                Some(local_file) => self.upload_new_file(local_file).await,
+                None => Ok(()),
+            },
+        }
+    }
+
+    async fn handle_existing_file(&self, file_wrapper: &FileWrapper) {
+        if file_wrapper.directory || file_wrapper.trashed {
+            return;
+        }
+        if let Err(error) = self.sync_existing_file(file_wrapper).await {
+            error!(
+                "Unable to sync file {}. {}",
+                file_wrapper.path.display(),
+                error
+            );
+            self.emit(SyncEvent::Failed {
+                path: file_wrapper.path.clone(),
+                error: error.to_string(),
+            });
+        }
+    }
+
+    async fn sync_existing_file(&self, file_wrapper: &FileWrapper) -> Result<()> {
+        let action = self.get_sync_action(file_wrapper)?;
+        match self.constrain_sync_action(file_wrapper, action) {
+            SyncAction::Nothing => Ok(()),
+            SyncAction::Download => self.create_file(file_wrapper).await,
+            SyncAction::Upload(local_file_wrapper) => self.upload_file(&local_file_wrapper).await,
+            SyncAction::DeleteRemote => {
+                debug!(
+                    "Moving {} to the remote's trash as it was deleted locally",
+                    file_wrapper.path.display()
+                );
+                self.remote.trash(file_wrapper).await?;
+                Ok(self.context.delete_file(&file_wrapper.id)?)
+            }
+        }
+    }
+
+    /// A root directory that is missing or empty although files have been synced to it is most
+    /// likely an unmounted disk, which would otherwise look like every file had been deleted
+    /// locally, so the cycle is stopped before anything is changed.
+    fn check_root_dir(&self) -> Result<()> {
+        let has_synced_files = self
+            .context
+            .get_all_files()?
+            .iter()
+            .any(|file| !file.directory && as_secs(file.last_accessed) != 0);
+        if !has_synced_files {
+            return Ok(());
+        }
+        let is_empty = match read_dir(&self.config.root_dir) {
+            Ok(entries) => entries
+                .filter_map(|entry| entry.ok())
+                .all(|entry| entry.file_name() == trash::TRASH_DIRECTORY),
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => true,
+            Err(error) => return Err(error.into()),
+        };
+        if is_empty {
+            return Err(RdriveError::Path(
+                self.config.root_dir.clone(),
+                "Root directory is missing or empty although files have been synced to it, so it \
+                 may not be mounted"
+                    .to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Limits what is done with a file to the directions the sync mode allows. Uploading
+    /// replaces remote changes with the local file and downloading reverts local changes.
+    fn constrain_sync_action(&self, file_wrapper: &FileWrapper, action: SyncAction) -> SyncAction {
+        match (self.config.mode, action) {
+            (SyncMode::UploadOnly, SyncAction::Download) => {
+                let is_uploadable = file_wrapper.path.is_file()
+                    && file_wrapper.can_edit
+                    && file_wrapper.shortcut_target_id.is_none()
+                    && file_wrapper.symlink_target.is_none()
+                    && !link::is_google_native(&file_wrapper.mime_type);
+                if is_uploadable {
+                    debug!(
+                        "Replacing remote changes to {} as only uploads are allowed",
+                        file_wrapper.path.display()
+                    );
+                    SyncAction::Upload(Box::new(file_wrapper.clone()))
+                } else {
+                    SyncAction::Nothing
+                }
             }
-            Err(e) => {
-                error!("Error: {}", e);
-                Vec::new()
+            // Google files only have links or exported copies locally, so aren't deleted with them.
+            (SyncMode::UploadOnly, SyncAction::DeleteRemote)
+                if !self.config.delete_remote
+                    || !file_wrapper.can_edit
+                    || link::is_google_native(&file_wrapper.mime_type) =>
+            {
+                SyncAction::Nothing
             }
+            (SyncMode::DownloadOnly, SyncAction::Upload(_)) => {
+                debug!(
+                    "Reverting local changes to {} as only downloads are allowed",
+                    file_wrapper.path.display()
+                );
+                SyncAction::Download
+            }
+            (SyncMode::TwoWay, SyncAction::DeleteRemote)
+            | (SyncMode::DownloadOnly, SyncAction::DeleteRemote) => SyncAction::Download,
+            (_, action) => action,
         }
     }
 
-    pub async fn store_fetched_files(&self) -> Result<(), rusqlite::Error> {
-        let fetched_files = self.fetch_files(None).await;
+    /// Decides what to do with a file from which side has changed since the last sync, before the
+    /// sync mode is taken into account.
+    fn get_sync_action(&self, file_wrapper: &FileWrapper) -> Result<SyncAction> {
+        if let Some(target_id) = &file_wrapper.shortcut_target_id {
+            // The symlink is relative, so is recreated when its target has moved even though the
+            // shortcut itself hasn't changed.
+            let is_synced = as_secs(file_wrapper.last_accessed) != 0
+                && fs::read_link(&file_wrapper.path).ok()
+                    == Some(self.get_shortcut_symlink_target(file_wrapper, target_id)?);
+            if is_synced {
+                return Ok(SyncAction::Nothing);
+            }
+            return Ok(SyncAction::Download);
+        }
+        if let Some(symlink_target) = &file_wrapper.symlink_target {
+            return Ok(self.get_stored_symlink_action(file_wrapper, symlink_target));
+        }
+        if !file_wrapper.path.exists() {
+            if as_secs(file_wrapper.last_accessed) != 0 {
+                debug!(
+                    "File {} has been deleted locally since last sync",
+                    file_wrapper.path.display()
+                );
+                return Ok(SyncAction::DeleteRemote);
+            }
+            debug!(
+                "Creating file {} for the first time",
+                file_wrapper.path.display()
+            );
+            return Ok(SyncAction::Download);
+        }
+        let local_modified_time = as_secs(file_wrapper.path.metadata()?.modified()?);
+        let remote_modified_time = as_secs(file_wrapper.last_accessed);
+        if remote_modified_time == 0 {
+            debug!(
+                "Remote modified time wasn't updated properly for file {} when it was created",
+                file_wrapper.path.display()
+            );
+            Ok(SyncAction::Download)
+        } else if local_modified_time > remote_modified_time
+            && self.config.mode == SyncMode::DownloadOnly
+        {
+            // Checked before the cases below that leave local changes alone, as a download-only
+            // directory is always kept a mirror of the remote.
+            debug!(
+                "Reverting local changes to {} as only downloads are allowed",
+                file_wrapper.path.display()
+            );
+            Ok(SyncAction::Download)
+        } else if local_modified_time > remote_modified_time && !file_wrapper.can_edit {
+            debug!(
+                "Not uploading {} as it can't be changed on the remote",
+                file_wrapper.path.display()
+            );
+            Ok(SyncAction::Nothing)
+        } else if local_modified_time > remote_modified_time
+            && self.context.get_import_path(&file_wrapper.id)?.is_some()
+        {
+            debug!(
+                "Imported file {} has changed locally since last sync",
+                file_wrapper.path.display()
+            );
+            let local_file_wrapper = FileWrapper {
+                mime_type: Drive::guess_mime_type(&file_wrapper.path),
+                ..file_wrapper.clone()
+            };
+            Ok(SyncAction::Upload(Box::new(local_file_wrapper)))
+        } else if local_modified_time > remote_modified_time
+            && self.config.export.contains_key(&file_wrapper.mime_type)
+        {
+            debug!(
+                "Not uploading {} as exported copies of Google files are read only",
+                file_wrapper.path.display()
+            );
+            Ok(SyncAction::Nothing)
+        } else if local_modified_time > remote_modified_time
+            && link::is_google_native(&file_wrapper.mime_type)
+        {
+            debug!(
+                "Restoring link {} as links to Google files can't be edited",
+                file_wrapper.path.display()
+            );
+            Ok(SyncAction::Download)
+        } else if local_modified_time > remote_modified_time {
+            debug!(
+                "File {} has changed locally since last sync",
+                file_wrapper.path.display()
+            );
+            Ok(SyncAction::Upload(Box::new(file_wrapper.clone())))
+        } else if local_modified_time < remote_modified_time {
+            debug!(
+                "File {} has changed on remote since last sync",
+                file_wrapper.path.display()
+            );
+            Ok(SyncAction::Download)
+        } else {
+            debug!("Nothing to do for file {}", file_wrapper.path.display());
+            Ok(SyncAction::Nothing)
+        }
+    }
+
+    /// Stored symlinks are recreated whatever the symlink policy is, but changes to where they
+    /// point are only uploaded when symlinks are being stored.
+    fn get_stored_symlink_action(
+        &self,
+        file_wrapper: &FileWrapper,
+        symlink_target: &str,
+    ) -> SyncAction {
+        let local_target = match fs::read_link(&file_wrapper.path) {
+            Ok(local_target) if as_secs(file_wrapper.last_accessed) != 0 => local_target,
+            _ => return SyncAction::Download,
+        };
+        if local_target == Path::new(symlink_target) {
+            return SyncAction::Nothing;
+        }
+        if self.config.mode == SyncMode::DownloadOnly {
+            return SyncAction::Download;
+        }
+        if self.config.symlinks != SymlinkPolicy::Store {
+            return SyncAction::Nothing;
+        }
+        debug!(
+            "Symlink {} has changed locally since last sync",
+            file_wrapper.path.display()
+        );
+        let local_file_wrapper = FileWrapper {
+            symlink_target: local_target.to_str().map(str::to_string),
+            ..file_wrapper.clone()
+        };
+        SyncAction::Upload(Box::new(local_file_wrapper))
+    }
+
+    pub async fn store_fetched_files(&self) -> Result<()> {
+        let fetched_files = self.list_remote_files().await?;
+        self.store_remote_files(&fetched_files)
+    }
+
+    /// Lists the remote files under the configured remote folder, or every remote file if no
+    /// folder is configured. The folder's children are listed as if they were at the top of the
+    /// remote, so they are synced straight into the root directory.
+    async fn list_remote_files(&self) -> Result<Vec<RemoteFile>> {
+        let files = self.remote.list_files().await?;
+        self.scope_to_remote_folder(files)
+    }
+
+    fn scope_to_remote_folder(&self, files: Vec<RemoteFile>) -> Result<Vec<RemoteFile>> {
+        let remote_folder = match &self.config.remote_folder {
+            Some(remote_folder) => remote_folder,
+            None => return Ok(files),
+        };
+        let files_by_id: HashMap<String, RemoteFile> = files
+            .iter()
+            .map(|file| (file.id.clone(), file.clone()))
+            .collect();
+        self.remote_folder_id.replace(None);
+        let folder = Drive::find_remote_folder(remote_folder, &files_by_id).ok_or_else(|| {
+            RdriveError::Config(format!("Remote folder {} wasn't found", remote_folder))
+        })?;
+        *self.remote_folder_id.borrow_mut() = Some(folder.id.clone());
+        let scoped_files = files
+            .into_iter()
+            .filter(|file| Drive::is_ancestor(folder, file, &files_by_id))
+            .collect();
+        Ok(scoped_files)
+    }
+
+    /// Finds a folder by its ID, or by its path from the top of the remote.
+    fn find_remote_folder<'a>(
+        remote_folder: &str,
+        files_by_id: &'a HashMap<String, RemoteFile>,
+    ) -> Option<&'a RemoteFile> {
+        if let Some(folder) = files_by_id.get(remote_folder) {
+            return Some(folder).filter(|folder| folder.mime_type == DIRECTORY_MIME_TYPE);
+        }
+        let components: Vec<&str> = remote_folder
+            .split('/')
+            .filter(|component| !component.is_empty())
+            .collect();
+        files_by_id.values().find(|file| {
+            if file.mime_type != DIRECTORY_MIME_TYPE || file.trashed {
+                return false;
+            }
+            let mut names = vec![file.name.as_str()];
+            let mut visited = HashSet::new();
+            let mut current = *file;
+            while let Some(parent) = Drive::get_primary_parent(current, files_by_id) {
+                if !visited.insert(parent.id.as_str()) {
+                    return false;
+                }
+                names.insert(0, parent.name.as_str());
+                current = parent;
+            }
+            names == components
+        })
+    }
+
+    fn store_remote_files(&self, fetched_files: &[RemoteFile]) -> Result<()> {
         let mut files_by_id = HashMap::new();
-        let borrowed_files: &Vec<File> = fetched_files.borrow();
-        for file in borrowed_files {
-            files_by_id.insert(file.id.clone().unwrap(), file.clone());
-        }
-        let stored_files_result = self.context.transaction(|| -> Result<(), rusqlite::Error> {
-            for file in borrowed_files {
-                let mut path = self.config.root_dir.clone();
-                path.push(self.get_path(file, &files_by_id));
-                if self.should_be_ignored(&path) {
-                    continue;
+        for file in fetched_files {
+            files_by_id.insert(file.id.clone(), file.clone());
+        }
+        let imports = self.context.get_imports()?;
+        let stored_suffixes = self.context.get_name_suffixes()?;
+        let remote_names = self.context.get_remote_names()?;
+        let mut shortcut_target_ids = HashMap::new();
+        for file in fetched_files {
+            if let Some(target_id) = self.get_shortcut_target_id(file, &files_by_id) {
+                shortcut_target_ids.insert(file.id.clone(), target_id);
+            }
+        }
+        let (local_names, suffixes) = self.get_local_names(
+            fetched_files,
+            &files_by_id,
+            &shortcut_target_ids,
+            &stored_suffixes,
+        );
+        let stored_files: HashMap<String, FileWrapper> = self
+            .context
+            .get_all_files()?
+            .into_iter()
+            .map(|file| (file.id.clone(), file))
+            .collect();
+        let file_paths: Vec<(&RemoteFile, PathBuf)> = fetched_files
+            .iter()
+            .map(|file| {
+                let path = match imports.get(&file.id) {
+                    Some(import_path) => import_path.clone(),
+                    None => {
+                        self.config
+                            .root_dir
+                            .join(self.get_path(file, &files_by_id, &local_names))
+                    }
+                };
+                (file, path)
+            })
+            .filter(|(_, path)| !self.should_be_ignored(path) && !self.is_trash(path))
+            .collect();
+        let moved_ids = self.move_renamed_files(&file_paths, &stored_files);
+        let stored_files_result = self.context.transaction(|| -> rusqlite::Result<()> {
+            for (file, path) in &file_paths {
+                let mut file_wrapper = Drive::convert_to_file_wrapper(file, path);
+                file_wrapper.shortcut_target_id = shortcut_target_ids.get(&file.id).cloned();
+                if let Some(stored_file) = stored_files.get(&file.id) {
+                    if moved_ids.contains(&file.id) {
+                        file_wrapper.last_accessed = stored_file.last_accessed;
+                    } else if let Some(last_accessed) =
+                        conflict::resolve(self.config.conflict, stored_file, &file_wrapper)
+                    {
+                        file_wrapper.last_accessed = last_accessed;
+                    }
                 }
-                let file_wrapper = Drive::convert_to_file_wrapper(file, &path);
                 self.context.store_file(&file_wrapper)?;
+                let is_renamed =
+                    path.file_name().and_then(|name| name.to_str()) != Some(file.name.as_str());
+                let remote_name = (path.clone(), file.name.clone());
+                if is_renamed
+                    && !imports.contains_key(&file.id)
+                    && !link::is_google_native(&file.mime_type)
+                {
+                    if remote_names.get(&file.id) != Some(&remote_name) {
+                        self.context.store_remote_name(&file.id, path, &file.name)?;
+                    }
+                } else if remote_names.contains_key(&file.id) {
+                    self.context.delete_remote_name(&file.id)?;
+                }
+            }
+            for (id, suffix) in &suffixes {
+                if stored_suffixes.get(id) != Some(suffix) {
+                    self.context.store_name_suffix(id, *suffix)?;
+                }
             }
             Ok(())
         });
-        if stored_files_result.is_err() {
-            error!(
-                "Failed to store files {}",
-                fetched_files
-                    .into_iter()
-                    .map(|x| x.name.unwrap_or("".to_string()))
-                    .collect::<Vec<String>>()
-                    .join(", ")
-            );
+        Ok(stored_files_result?)
+    }
+
+    /// Moves local files whose path changed without their content changing, such as when the
+    /// suffix given to a duplicate name changes, so they aren't downloaded again to the new path
+    /// while the old copy is uploaded as a new file. Folders are moved before their contents, so
+    /// files that moved with their folder are found at their new path. Returns the IDs of the
+    /// files now at their new path.
+    fn move_renamed_files(
+        &self,
+        file_paths: &[(&RemoteFile, PathBuf)],
+        stored_files: &HashMap<String, FileWrapper>,
+    ) -> HashSet<String> {
+        let mut renamed_files: Vec<(&FileWrapper, &PathBuf)> = file_paths
+            .iter()
+            .filter_map(|(file, path)| {
+                let stored_file = stored_files.get(&file.id)?;
+                let is_only_path_changed = stored_file.path != *path
+                    && stored_file.version == file.version
+                    && stored_file.last_modified == file.modified_time
+                    && as_secs(stored_file.last_accessed) != 0;
+                Some((stored_file, path)).filter(|_| is_only_path_changed)
+            })
+            .collect();
+        renamed_files.sort_by_key(|(_, path)| path.components().count());
+        let mut moved_ids = HashSet::new();
+        for (stored_file, path) in renamed_files {
+            let is_old_path_present = fs::symlink_metadata(&stored_file.path).is_ok();
+            if fs::symlink_metadata(path).is_ok() {
+                if !is_old_path_present {
+                    moved_ids.insert(stored_file.id.clone());
+                }
+                continue;
+            }
+            if !is_old_path_present {
+                continue;
+            }
+            let move_result = match path.parent() {
+                Some(parent) => fs::create_dir_all(parent),
+                None => Ok(()),
+            }
+            .and_then(|_| fs::rename(&stored_file.path, path));
+            match move_result {
+                Ok(()) => {
+                    debug!(
+                        "Moved {} to {} to match the remote",
+                        stored_file.path.display(),
+                        path.display()
+                    );
+                    moved_ids.insert(stored_file.id.clone());
+                }
+                Err(error) => warn!(
+                    "Failed to move {} to {} - {}",
+                    stored_file.path.display(),
+                    path.display(),
+                    error
+                ),
+            }
         }
-        Ok(())
+        moved_ids
+    }
+
+    fn is_trash(&self, path: &Path) -> bool {
+        path.starts_with(self.config.root_dir.join(trash::TRASH_DIRECTORY))
     }
 
     fn should_be_ignored(&self, path: &Path) -> bool {
@@ -115,128 +926,464 @@ impl Drive {
             .any(|pattern| pattern.matches_path(path))
     }
 
-    fn get_path(&self, file: &File, files_by_id: &HashMap<String, File>) -> PathBuf {
-        let parents = file.parents.as_ref();
-        if parents.is_none() {
-            let file_name = Drive::clean_file_name(&file.name);
-            return PathBuf::from(file_name);
-        }
-        let parent_id = parents.unwrap().first();
-        if parent_id.is_none() {
-            let file_name = Drive::clean_file_name(&file.name);
-            return PathBuf::from(file_name);
-        }
-        let mut parent = files_by_id.get(parent_id.unwrap());
-        if parent.is_none() {
-            let file_name = Drive::clean_file_name(&file.name);
-            return PathBuf::from(file_name);
-        }
-        let parent_name = parent.unwrap().clone().name;
-        if parent_name.is_none() {
-            let file_name = Drive::clean_file_name(&file.name);
-            return PathBuf::from(file_name);
-        }
-        let mut path = PathBuf::new();
-        path.push(Drive::clean_file_name(&parent_name));
-        while parent.is_some() {
-            if let Some(parents) = parent.unwrap().parents.as_ref() {
-                if let Some(parent_id) = parents.first() {
-                    parent = files_by_id.get(parent_id);
-                    if let Some(parent_file) = parent {
-                        let parent_name = Drive::clean_file_name(&parent_file.name);
-                        path = Path::new(&parent_name).join(path.as_path());
+    /// Builds the path of `file` relative to the root directory. Files with more than one parent
+    /// are only mirrored once, under the first parent that is part of the listing, so the same
+    /// file is never synced to two places. Shared files that aren't in any of the user's folders
+    /// are put under the shared with me directory.
+    fn get_path(
+        &self,
+        file: &RemoteFile,
+        files_by_id: &HashMap<String, RemoteFile>,
+        local_names: &HashMap<String, String>,
+    ) -> PathBuf {
+        let get_name = |file: &RemoteFile| {
+            local_names
+                .get(&file.id)
+                .cloned()
+                .unwrap_or_else(|| naming::sanitise(&file.name, self.config.name_profile))
+        };
+        let mut path = PathBuf::from(get_name(file));
+        let mut visited = HashSet::new();
+        visited.insert(file.id.as_str());
+        let mut current = file;
+        while let Some(parent) = Drive::get_primary_parent(current, files_by_id) {
+            if !visited.insert(parent.id.as_str()) {
+                error!("Found a cycle in the parents of {}", file.name);
+                break;
+            }
+            path = Path::new(&get_name(parent)).join(path);
+            current = parent;
+        }
+        if current.parents.is_empty() && !current.owned_by_me {
+            path = Path::new(SHARED_WITH_ME_DIRECTORY).join(path);
+        }
+        path
+    }
+
+    /// Works out the local name of every file. Drive allows files with the same name in one
+    /// folder, so all but one of them get a numbered suffix. On case-insensitive file systems,
+    /// names that only differ by case count as the same. A file keeps the suffix it was given
+    /// before for as long as no other file has claimed it, and new suffixes are handed out in
+    /// order of file ID, skipping any that would clash with another file's name.
+    fn get_local_names(
+        &self,
+        files: &[RemoteFile],
+        files_by_id: &HashMap<String, RemoteFile>,
+        shortcut_target_ids: &HashMap<String, String>,
+        stored_suffixes: &HashMap<String, usize>,
+    ) -> (HashMap<String, String>, HashMap<String, usize>) {
+        let case_insensitive = self.is_case_insensitive();
+        let get_name_key = |name: String| {
+            if case_insensitive {
+                name.to_lowercase()
+            } else {
+                name
+            }
+        };
+        let get_local_name = |file: &RemoteFile, suffix: usize| {
+            self.get_local_name(file, suffix, shortcut_target_ids.contains_key(&file.id))
+        };
+        let mut local_names = HashMap::new();
+        let mut suffixes = HashMap::new();
+        let mut files_by_name: HashMap<(String, String), Vec<&RemoteFile>> = HashMap::new();
+        for file in files {
+            if file.trashed {
+                local_names.insert(file.id.clone(), get_local_name(file, 0));
+                continue;
+            }
+            let parent_key = match Drive::get_primary_parent(file, files_by_id) {
+                Some(parent) => parent.id.clone(),
+                None if file.parents.is_empty() && !file.owned_by_me => {
+                    SHARED_WITH_ME_DIRECTORY.to_string()
+                }
+                None => String::new(),
+            };
+            files_by_name
+                .entry((parent_key, get_name_key(get_local_name(file, 0))))
+                .or_default()
+                .push(file);
+        }
+        let taken_names: HashSet<(String, String)> = files_by_name.keys().cloned().collect();
+        for ((parent_key, _), mut same_name_files) in files_by_name {
+            same_name_files.sort_by(|a, b| a.id.cmp(&b.id));
+            let is_free = |file: &RemoteFile, suffix: usize| {
+                suffix == 0
+                    || !taken_names.contains(&(
+                        parent_key.clone(),
+                        get_name_key(get_local_name(file, suffix)),
+                    ))
+            };
+            let mut claimed = HashSet::new();
+            for file in &same_name_files {
+                if let Some(suffix) = stored_suffixes.get(&file.id) {
+                    if is_free(file, *suffix) && claimed.insert(*suffix) {
+                        suffixes.insert(file.id.clone(), *suffix);
                     }
-                } else {
-                    parent = None;
                 }
-            } else {
-                parent = None;
             }
+            for file in &same_name_files {
+                if !suffixes.contains_key(&file.id) {
+                    let suffix = (0..)
+                        .find(|suffix| !claimed.contains(suffix) && is_free(file, *suffix))
+                        .unwrap();
+                    claimed.insert(suffix);
+                    suffixes.insert(file.id.clone(), suffix);
+                }
+                local_names.insert(file.id.clone(), get_local_name(file, suffixes[&file.id]));
+            }
+            let is_new_collision = same_name_files
+                .iter()
+                .any(|file| stored_suffixes.get(&file.id) != Some(&suffixes[&file.id]));
+            if is_new_collision
+                && same_name_files
+                    .iter()
+                    .any(|file| file.name != same_name_files[0].name)
+            {
+                warn!(
+                    "{} only differ by case, so have been given different local names",
+                    same_name_files
+                        .iter()
+                        .map(|file| local_names[&file.id].as_str())
+                        .collect::<Vec<&str>>()
+                        .join(", ")
+                );
+            }
+        }
+        (local_names, suffixes)
+    }
+
+    /// Whether names that only differ by case are the same file in the root directory, as on the
+    /// default file systems of Windows and macOS.
+    fn is_case_insensitive(&self) -> bool {
+        *self.case_insensitive.get_or_init(|| {
+            naming::is_case_insensitive(&self.config.root_dir).unwrap_or_else(|error| {
+                error!(
+                    "Unable to tell whether {} is case-insensitive, assuming it is. {}",
+                    self.config.root_dir.display(),
+                    error
+                );
+                true
+            })
+        })
+    }
+
+    /// The local name of a file, including the extension added to exported copies and links.
+    fn get_local_name(&self, file: &RemoteFile, suffix: usize, is_symlink: bool) -> String {
+        let extension = if let Some(format) = self.config.export.get(&file.mime_type) {
+            Some(format.extension.to_string())
+        } else if !is_symlink && link::is_google_native(&file.mime_type) {
+            Some(link::get_link_extension(
+                &file.mime_type,
+                self.config.link_format,
+            ))
+        } else {
+            None
+        };
+        let split_extension =
+            file.mime_type != DIRECTORY_MIME_TYPE && !link::is_google_native(&file.mime_type);
+        let reserved_bytes = extension
+            .as_ref()
+            .map_or(0, |extension| extension.len() + 1);
+        let name = naming::get_local_name(
+            &file.name,
+            self.config.name_profile,
+            suffix,
+            split_extension,
+            reserved_bytes,
+        );
+        match extension {
+            Some(extension) => format!("{}.{}", name, extension),
+            None => name,
+        }
+    }
+
+    /// The ID of the file a shortcut should be synced as a symlink to. Shortcuts to files outside
+    /// the synced tree, to missing files, to their own ancestors or in a loop of shortcuts are
+    /// synced as links instead.
+    fn get_shortcut_target_id(
+        &self,
+        file: &RemoteFile,
+        files_by_id: &HashMap<String, RemoteFile>,
+    ) -> Option<String> {
+        let mut target_id = file.shortcut_target_id.as_ref()?;
+        let mut visited = HashSet::new();
+        visited.insert(file.id.as_str());
+        let target = loop {
+            if !visited.insert(target_id) {
+                debug!("Found a loop of shortcuts from {}", file.name);
+                return None;
+            }
+            let target = files_by_id.get(target_id)?;
+            match &target.shortcut_target_id {
+                Some(next_target_id) => target_id = next_target_id,
+                None => break target,
+            }
+        };
+        if target.trashed || !(target.owned_by_me || self.config.shared_with_me) {
+            return None;
+        }
+        let target_path = self.get_path(target, files_by_id, &HashMap::new());
+        if self.should_be_ignored(&self.config.root_dir.join(target_path)) {
+            return None;
+        }
+        if Drive::is_ancestor(target, file, files_by_id) {
+            debug!("{} is a shortcut to one of its own folders", file.name);
+            return None;
+        }
+        Some(target.id.clone())
+    }
+
+    fn is_ancestor(
+        ancestor: &RemoteFile,
+        file: &RemoteFile,
+        files_by_id: &HashMap<String, RemoteFile>,
+    ) -> bool {
+        let mut visited = HashSet::new();
+        let mut current = file;
+        while let Some(parent) = Drive::get_primary_parent(current, files_by_id) {
+            if parent.id == ancestor.id {
+                return true;
+            }
+            if !visited.insert(parent.id.as_str()) {
+                return false;
+            }
+            current = parent;
         }
-        path.join(Drive::clean_file_name(&file.name))
+        false
+    }
+
+    fn get_primary_parent<'a>(
+        file: &RemoteFile,
+        files_by_id: &'a HashMap<String, RemoteFile>,
+    ) -> Option<&'a RemoteFile> {
+        if file.parents.len() > 1 {
+            debug!(
+                "{} has {} parents, only the first known parent will be used",
+                file.name,
+                file.parents.len()
+            );
+        }
+        file.parents
+            .iter()
+            .find_map(|parent_id| files_by_id.get(parent_id))
+    }
+
+    pub fn get_all_files(&self, owned_only: bool) -> Result<Vec<FileWrapper>> {
+        let all_files: Vec<FileWrapper> = self.context.get_all_files()?;
+        if !owned_only {
+            return Ok(all_files);
+        }
+        let mut filtered_files = Vec::new();
+        for file in all_files {
+            if file.owned_by_me {
+                filtered_files.push(file);
+            }
+        }
+        Ok(filtered_files)
+    }
+
+    /// The format of an exported copy is taken from its path, which was named after the configured
+    /// format or, for imported files, is the original local file.
+    fn get_export_format(file_wrapper: &FileWrapper) -> Option<ExportFormat> {
+        let extension = file_wrapper.path.extension()?.to_str()?;
+        export::find_export_format(&file_wrapper.mime_type, extension)
+    }
+
+    pub async fn create_file(&self, file_wrapper: &FileWrapper) -> Result<()> {
+        if let Some(target_id) = &file_wrapper.shortcut_target_id {
+            return self.create_shortcut(file_wrapper, target_id);
+        }
+        if let Some(symlink_target) = &file_wrapper.symlink_target {
+            debug!(
+                "Creating symlink {} to {}",
+                file_wrapper.path.display(),
+                symlink_target
+            );
+            return self.write_symlink(file_wrapper, Path::new(symlink_target));
+        }
+        let path = file_wrapper.path.clone();
+        if let Some(parent) = path.parent() {
+            create_dir_all(parent)?;
+        }
+        // The content is fetched before the local copy is touched, so a failed download leaves
+        // it where it was rather than only in the trash.
+        let bytes = if let Some(format) = Drive::get_export_format(file_wrapper) {
+            self.remote.export(file_wrapper, format.mime_type).await?
+        } else if link::is_google_native(&file_wrapper.mime_type) {
+            link::get_link_content(file_wrapper, self.config.link_format)?.into_bytes()
+        } else {
+            self.remote.download(file_wrapper).await?
+        };
+        if path.exists() {
+            Drive::set_read_only(&path, false)?;
+            if self.config.trash && fs::symlink_metadata(&path)?.is_file() {
+                trash::move_to_trash(&self.config.root_dir, &path)?;
+            }
+        }
+        Drive::write_to_file(&path, &bytes)?;
+        Drive::set_modified(&path, file_wrapper.last_modified.into())?;
+        self.write_xattrs(file_wrapper);
+        if self.config.preserve_permissions {
+            permissions::apply(
+                &path,
+                &file_wrapper.permissions,
+                self.config.umask,
+                self.config.preserve_owner,
+            )?;
+        }
+        if !file_wrapper.can_edit {
+            Drive::set_read_only(&path, true)?;
+        }
+        let modified = path.metadata()?.modified()?;
+        self.context
+            .update_last_accessed(&file_wrapper.id, &modified)?;
+        debug!(
+            "Updated last accessed for {} successfully",
+            file_wrapper.path.display()
+        );
+        self.emit(SyncEvent::Downloaded {
+            path: file_wrapper.path.clone(),
+        });
+        Ok(())
+    }
+
+    fn create_shortcut(&self, file_wrapper: &FileWrapper, target_id: &String) -> Result<()> {
+        let target = self.get_shortcut_symlink_target(file_wrapper, target_id)?;
+        debug!(
+            "Creating shortcut {} to {}",
+            file_wrapper.path.display(),
+            target.display()
+        );
+        self.write_symlink(file_wrapper, &target)
     }
 
-    fn clean_file_name(file_name: &Option<String>) -> String {
-        match env::consts::OS {
-            "windows" => file_name.as_ref().unwrap().replace('\\', "_"),
-            "linux" => file_name.as_ref().unwrap().replace('/', "_"),
-            "macos" => file_name.as_ref().unwrap().replace('/', "_"),
-            _ => file_name.clone().unwrap(),
-        }
+    /// Where the symlink for a shortcut should point, relative to the shortcut's directory.
+    fn get_shortcut_symlink_target(
+        &self,
+        file_wrapper: &FileWrapper,
+        target_id: &String,
+    ) -> Result<PathBuf> {
+        let path = &file_wrapper.path;
+        let target = self.context.get_file(target_id)?.ok_or_else(|| {
+            RdriveError::Path(
+                path.clone(),
+                format!("Shortcut target {} isn't synced", target_id),
+            )
+        })?;
+        let parent = path
+            .parent()
+            .ok_or_else(|| RdriveError::Path(path.clone(), "Shortcut has no parent".to_string()))?;
+        Ok(link::get_relative_path(parent, &target.path))
     }
 
-    pub fn get_all_files(&self, owned_only: bool) -> Result<Vec<FileWrapper>, std::io::Error> {
-        let all_files: Vec<FileWrapper> = self.context.get_all_files().unwrap();
-        if !owned_only {
-            return Ok(all_files);
+    /// Replaces whatever symlink is at the file's path with one to `target`. Anything else at
+    /// the path is left alone.
+    fn write_symlink(&self, file_wrapper: &FileWrapper, target: &Path) -> Result<()> {
+        let path = &file_wrapper.path;
+        if let Some(parent) = path.parent() {
+            create_dir_all(parent)?;
         }
-        let mut filtered_files = Vec::new();
-        for file in all_files {
-            if file.owned_by_me {
-                filtered_files.push(file);
+        if let Ok(metadata) = fs::symlink_metadata(path) {
+            if !metadata.file_type().is_symlink() {
+                return Err(RdriveError::Path(
+                    path.clone(),
+                    "A file already exists where the symlink should be".to_string(),
+                ));
             }
+            fs::remove_file(path)?;
         }
-        Ok(filtered_files)
+        Drive::symlink(target, path)?;
+        let modified = fs::symlink_metadata(path)?.modified()?;
+        self.context
+            .update_last_accessed(&file_wrapper.id, &modified)?;
+        self.emit(SyncEvent::Downloaded { path: path.clone() });
+        Ok(())
     }
 
-    pub async fn create_file(
-        &self,
-        file_wrapper: &FileWrapper,
-    ) -> Result<(), Box<dyn std::error::Error>> {
-        let path = file_wrapper.path.clone();
-        let create_dirs_result = create_dir_all(path.parent().unwrap());
-        if let Err(error) = create_dirs_result {
-            error!(
-                "Failed to create directory {} with error {}",
-                &path.parent().unwrap().display(),
+    /// Records which remote file a local file was synced with, so it can be matched up again by
+    /// [`Drive::rebuild`]. File systems without extended attributes are only logged.
+    fn write_xattrs(&self, file_wrapper: &FileWrapper) {
+        if !self.config.xattrs {
+            return;
+        }
+        let path = &file_wrapper.path;
+        let mut result = xattr::set(path, xattr::ID_ATTRIBUTE, &file_wrapper.id);
+        if let Some(version) = &file_wrapper.version {
+            result = result.and_then(|_| xattr::set(path, xattr::VERSION_ATTRIBUTE, version));
+        }
+        if let Err(error) = result {
+            debug!(
+                "Unable to write extended attributes to {}. {}",
+                path.display(),
                 error
             );
         }
-        if !file_wrapper.mime_type.contains("google") {
-            let response = self
-                .hub
-                .files()
-                .get(file_wrapper.id.as_ref())
-                .param("alt", "media")
-                .add_scope(Scope::Full)
-                .doit()
-                .await;
-            if let Ok(resp) = response {
-                <Drive>::write_to_file(&path, resp).await?;
-            }
+    }
+
+    /// Gives a downloaded file the time it was last modified on the remote, so local tools see
+    /// when it was last edited rather than when it was downloaded.
+    fn set_modified(path: &Path, modified: SystemTime) -> Result<()> {
+        let file = fs::OpenOptions::new().write(true).open(path)?;
+        file.set_modified(modified)?;
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    fn symlink(target: &Path, path: &Path) -> Result<()> {
+        Ok(std::os::unix::fs::symlink(target, path)?)
+    }
+
+    #[cfg(windows)]
+    fn symlink(target: &Path, path: &Path) -> Result<()> {
+        let resolved_target = path.parent().unwrap_or(path).join(target);
+        if resolved_target.is_dir() {
+            Ok(std::os::windows::fs::symlink_dir(target, path)?)
+        } else {
+            Ok(std::os::windows::fs::symlink_file(target, path)?)
+        }
+    }
+
+    #[cfg(not(any(unix, windows)))]
+    fn symlink(_target: &Path, path: &Path) -> Result<()> {
+        Err(RdriveError::Path(
+            path.to_path_buf(),
+            "Symlinks aren't supported on this platform".to_string(),
+        ))
+    }
+
+    /// Only the owner's write permission is restored, rather than making the file writable by
+    /// everyone.
+    #[cfg(unix)]
+    fn set_read_only(path: &Path, read_only: bool) -> Result<()> {
+        use std::os::unix::fs::PermissionsExt;
+
+        let mut permissions = path.metadata()?.permissions();
+        let mode = if read_only {
+            permissions.mode() & !0o222
         } else {
-            <Drive>::write_to_google_file(file_wrapper, &path)?;
+            permissions.mode() | 0o200
         };
-        let metadata = path.metadata();
-        if metadata.is_err() {
-            error!("Somehow the file {} doesn't exist", path.display());
+        if mode != permissions.mode() {
+            permissions.set_mode(mode);
+            fs::set_permissions(path, permissions)?;
         }
-        let update_result = self
-            .context
-            .update_last_accessed(&file_wrapper.id, &metadata.unwrap().modified().unwrap());
-        match update_result {
-            Ok(_) => debug!(
-                "Updated last accessed for {} successfully",
-                file_wrapper.path.display()
-            ),
-            Err(error) => error!(
-                "Something went wrong during update for {}: {}",
-                file_wrapper.path.display(),
-                error
-            ),
+        Ok(())
+    }
+
+    #[cfg(not(unix))]
+    fn set_read_only(path: &Path, read_only: bool) -> Result<()> {
+        let mut permissions = path.metadata()?.permissions();
+        if permissions.readonly() != read_only {
+            permissions.set_readonly(read_only);
+            fs::set_permissions(path, permissions)?;
         }
         Ok(())
     }
 
-    async fn write_to_file(
-        path: &Path,
-        unwrapped_response: (Response<Body>, File),
-    ) -> Result<(), Box<dyn std::error::Error>> {
+    fn write_to_file(path: &Path, bytes: &[u8]) -> Result<()> {
         debug!("Creating file {}", path.display());
         let mut file = fs::File::create(path)?;
-        let response = unwrapped_response.0;
-        let bytes = to_bytes(response.into_body()).await.unwrap();
-        file.write_all(&bytes)?;
+        file.write_all(bytes)?;
         match file.sync_all() {
             Ok(_) => {
                 debug!("Created file {}", path.display());
@@ -248,234 +1395,403 @@ impl Drive {
                     path.display(),
                     error
                 );
-                Err(Box::new(error))
+                Err(error.into())
             }
         }
     }
 
-    fn write_to_google_file(file_wrapper: &FileWrapper, path: &Path) -> Result<(), std::io::Error> {
-        debug!("Creating Google file {}", path.display());
-        let mut file_content: String = "#!/usr/bin/env bash\nxdg-open ".to_string();
-        file_content.push_str(file_wrapper.web_view_link.borrow().as_ref().unwrap());
-        let mut file = fs::File::create(path)?;
-        let write_result = file.write_all(file_content.as_bytes());
-        if let Err(error) = write_result {
-            error!(
-                "Failed to write data to Google file {} with error {}",
-                path.display(),
-                &error
-            );
-            return Err(error);
+    pub fn get_local_files(&self) -> Result<Vec<FileWrapper>> {
+        let mut ancestors = vec![fs::canonicalize(&self.config.root_dir)?];
+        self.read_local_dir(&self.config.root_dir, &mut ancestors)
+    }
+
+    /// `ancestors` holds the resolved paths of the directories being read, so symlinks that loop
+    /// back to one of them aren't followed.
+    fn read_local_dir(&self, dir: &Path, ancestors: &mut Vec<PathBuf>) -> Result<Vec<FileWrapper>> {
+        debug!("Traversing {}", dir.display());
+        let mut files = Vec::new();
+        for entry in read_dir(dir)? {
+            let entry_files = entry
+                .map_err(RdriveError::from)
+                .and_then(|entry| self.read_local_entry(&entry, ancestors));
+            match entry_files {
+                Ok(mut entry_files) => files.append(&mut entry_files),
+                Err(error) => {
+                    error!("Skipping local file in {}. {}", dir.display(), error);
+                    self.emit(SyncEvent::Failed {
+                        path: dir.to_path_buf(),
+                        error: error.to_string(),
+                    });
+                }
+            }
         }
-        match file.sync_all() {
-            Ok(_) => {
-                debug!("Created Google file {}", path.display());
-                Ok(())
+        Ok(files)
+    }
+
+    fn read_local_entry(
+        &self,
+        entry: &DirEntry,
+        ancestors: &mut Vec<PathBuf>,
+    ) -> Result<Vec<FileWrapper>> {
+        let path = entry.path();
+        let name = entry.file_name().into_string().map_err(|_| {
+            RdriveError::Path(path.clone(), "File name is not valid UTF-8".to_string())
+        })?;
+        if self.is_trash(&path) {
+            return Ok(vec![]);
+        }
+        if path.to_str().is_none() {
+            return Err(RdriveError::Path(
+                path,
+                "Path is not valid UTF-8".to_string(),
+            ));
+        }
+        let mut symlink_target = None;
+        // Symlinks created for Drive shortcuts are already synced, so are never followed.
+        let metadata = if !entry.file_type()?.is_symlink() || self.is_shortcut(&path)? {
+            entry.metadata()?
+        } else {
+            match self.config.symlinks {
+                SymlinkPolicy::Skip => {
+                    debug!("Skipping symlink {}", path.display());
+                    return Ok(vec![]);
+                }
+                SymlinkPolicy::Follow => fs::metadata(&path)?,
+                SymlinkPolicy::Store => {
+                    let target = fs::read_link(&path)?;
+                    symlink_target =
+                        Some(target.to_str().map(str::to_string).ok_or_else(|| {
+                            RdriveError::Path(
+                                path.clone(),
+                                "Symlink target is not valid UTF-8".to_string(),
+                            )
+                        })?);
+                    entry.metadata()?
+                }
             }
+        };
+        let modified = metadata.modified()?;
+        let last_modified = <DateTime<Local>>::from(modified);
+        let directory = metadata.is_dir();
+        let mime_type = if directory {
+            DIRECTORY_MIME_TYPE.to_string()
+        } else if symlink_target.is_some() {
+            mime::TEXT_PLAIN.essence_str().to_string()
+        } else {
+            Drive::guess_mime_type(&path)
+        };
+        let mut files = if directory {
+            self.read_local_subdir(&path, ancestors)
+        } else {
+            vec![]
+        };
+        files.push(FileWrapper {
+            id: String::new(),
+            name,
+            mime_type,
+            path,
+            directory,
+            web_view_link: None,
+            owned_by_me: true,
+            last_modified: <DateTime<FixedOffset>>::from(last_modified),
+            last_accessed: modified,
+            trashed: false,
+            version: None,
+            parents: Vec::new(),
+            can_edit: true,
+            shortcut_target_id: None,
+            permissions: Permissions::default(),
+            symlink_target,
+        });
+        Ok(files)
+    }
+
+    fn read_local_subdir(&self, dir: &Path, ancestors: &mut Vec<PathBuf>) -> Vec<FileWrapper> {
+        let resolved_dir = match fs::canonicalize(dir) {
+            Ok(resolved_dir) => resolved_dir,
             Err(error) => {
-                error!(
-                    "Failed to sync Google file {} with error {}",
-                    path.display(),
+                error!("Skipping directory {}. {}", dir.display(), error);
+                return vec![];
+            }
+        };
+        if ancestors.contains(&resolved_dir) {
+            debug!(
+                "Not following {} as it loops back to {}",
+                dir.display(),
+                resolved_dir.display()
+            );
+            return vec![];
+        }
+        ancestors.push(resolved_dir);
+        let files = self.read_local_dir(dir, ancestors).unwrap_or_else(|error| {
+            error!("Skipping directory {}. {}", dir.display(), error);
+            vec![]
+        });
+        ancestors.pop();
+        files
+    }
+
+    fn is_shortcut(&self, path: &Path) -> Result<bool> {
+        Ok(self
+            .context
+            .get_file_by_path(path)?
+            .is_some_and(|file_wrapper| file_wrapper.shortcut_target_id.is_some()))
+    }
+
+    fn guess_mime_type(path: &Path) -> String {
+        mime_guess::from_path(path)
+            .first()
+            .unwrap_or(mime::TEXT_PLAIN)
+            .essence_str()
+            .to_string()
+    }
+
+    pub async fn upload_file(&self, file_wrapper: &FileWrapper) -> Result<()> {
+        if link::is_google_native(&file_wrapper.mime_type) {
+            return Err(RdriveError::Path(
+                file_wrapper.path.clone(),
+                "Links and exported copies of Google files are never uploaded".to_string(),
+            ));
+        }
+        let file_wrapper = &self.with_local_permissions(file_wrapper)?;
+        let remote_file = if file_wrapper.id.is_empty() {
+            let parent_id = self.get_parent_id(file_wrapper)?;
+            self.remote
+                .upload(&self.with_remote_name(file_wrapper)?, parent_id.as_deref())
+                .await?
+        } else {
+            self.remote.update(file_wrapper).await?
+        };
+        if file_wrapper.symlink_target.is_none()
+            && self
+                .config
+                .keep_forever
+                .iter()
+                .any(|pattern| pattern.matches_path(&file_wrapper.path))
+        {
+            if let Err(error) = self.remote.keep_forever(&remote_file.id).await {
+                warn!(
+                    "Unable to keep the uploaded revision of {}. {}",
+                    file_wrapper.path.display(),
                     error
                 );
-                Err(error)
             }
         }
+        self.store_uploaded_file(&remote_file, file_wrapper)
     }
 
-    pub fn get_local_files(&self) -> Result<Vec<FileWrapper>, std::io::Error> {
-        self.read_local_dir(&self.config.root_dir)
+    /// The revisions the remote has kept of the synced file at `path`, oldest first.
+    pub async fn get_history(&self, path: &Path) -> Result<Vec<Revision>> {
+        let file_wrapper = self.get_synced_file(path)?;
+        self.remote.list_revisions(&file_wrapper).await
     }
 
-    fn read_local_dir(&self, dir: &PathBuf) -> Result<Vec<FileWrapper>, std::io::Error> {
-        debug!("Traversing {}", dir.display());
-        Ok(read_dir(dir)?
-            .flat_map(|res| {
-                res.into_iter().flat_map(|e| {
-                    let metadata = e.metadata().unwrap();
-                    let last_modified = <DateTime<Local>>::from(metadata.modified().unwrap());
-                    let mime_type = if e.file_type().unwrap().is_dir() {
-                        DIRECTORY_MIME_TYPE.to_string()
-                    } else {
-                        mime_guess::from_path(e.path().as_path())
-                            .first()
-                            .unwrap_or(mime::TEXT_PLAIN)
-                            .essence_str()
-                            .to_string()
-                    };
-                    let mut files = if e.file_type().unwrap().is_dir() {
-                        self.read_local_dir(&e.path()).unwrap_or(vec![])
-                    } else {
-                        vec![]
-                    };
-                    files.extend(vec![FileWrapper {
-                        id: String::new(),
-                        name: e.file_name().into_string().unwrap(),
-                        mime_type,
-                        path: e.path(),
-                        directory: e.file_type().unwrap().is_dir(),
-                        web_view_link: None,
-                        owned_by_me: true,
-                        last_modified: <DateTime<FixedOffset>>::from(last_modified),
-                        last_accessed: metadata.modified().unwrap(),
-                        trashed: false,
-                    }]);
-                    files
-                })
-            })
-            .collect::<Vec<FileWrapper>>())
+    /// Replaces the local file at `path` with an older revision, moving the current version to
+    /// the trash. The restored content is a local change, so is uploaded by the next sync, or
+    /// straight away with `upload` so it is the current version on the remote.
+    pub async fn restore_revision(
+        &self,
+        path: &Path,
+        revision_id: &str,
+        upload: bool,
+    ) -> Result<()> {
+        if upload && self.config.mode == SyncMode::DownloadOnly {
+            return Err(RdriveError::Config(
+                "Revisions can't be restored to the remote as only downloads are allowed"
+                    .to_string(),
+            ));
+        }
+        let file_wrapper = self.get_synced_file(path)?;
+        let bytes = self
+            .remote
+            .download_revision(&file_wrapper, revision_id)
+            .await?;
+        if path.exists() {
+            Drive::set_read_only(path, false)?;
+            if self.config.trash {
+                trash::move_to_trash(&self.config.root_dir, path)?;
+            }
+        }
+        Drive::write_to_file(path, &bytes)?;
+        Drive::set_modified(path, SystemTime::now())?;
+        if upload {
+            self.upload_file(&file_wrapper).await?;
+        }
+        Ok(())
     }
 
-    pub async fn upload_file(
-        &self,
-        file_wrapper: &FileWrapper,
-    ) -> Result<(), Box<dyn std::error::Error>> {
-        let fields = "id, kind, name, description, kind, mimeType, parents, ownedByMe, webContentLink, webViewLink, modifiedTime, trashed";
-        let response = self
-            .hub
-            .files()
-            .create(self.convert_to_file(file_wrapper))
-            .add_scope(Scope::Full)
-            .param("fields", fields)
-            .upload(
-                fs::File::open(&file_wrapper.path).unwrap(),
-                file_wrapper.mime_type.parse().unwrap(),
+    fn get_synced_file(&self, path: &Path) -> Result<FileWrapper> {
+        match self.context.get_file_by_path(path)? {
+            Some(file_wrapper)
+                if !file_wrapper.directory
+                    && file_wrapper.shortcut_target_id.is_none()
+                    && file_wrapper.symlink_target.is_none()
+                    && !link::is_google_native(&file_wrapper.mime_type) =>
+            {
+                Ok(file_wrapper)
+            }
+            _ => Err(RdriveError::Path(
+                path.to_path_buf(),
+                "Not a synced file with revisions".to_string(),
+            )),
+        }
+    }
+
+    /// Uploads a new local file converted to the Google-native `mime_type`, remembering where it
+    /// came from so later local changes update the same Google file.
+    pub async fn import_file(&self, file_wrapper: &FileWrapper, mime_type: &str) -> Result<()> {
+        let file_wrapper = &self.with_local_permissions(file_wrapper)?;
+        let parent_id = self.get_parent_id(file_wrapper)?;
+        let remote_file = self
+            .remote
+            .import(
+                &self.with_remote_name(file_wrapper)?,
+                parent_id.as_deref(),
+                mime_type,
             )
             .await?;
+        self.context
+            .store_import(&remote_file.id, &file_wrapper.path)?;
+        self.store_uploaded_file(&remote_file, file_wrapper)
+    }
+
+    /// Gives a new local file the Drive name it was synced from if its local name was sanitised,
+    /// so a file that is uploaded again isn't renamed on the remote.
+    fn with_remote_name(&self, file_wrapper: &FileWrapper) -> Result<FileWrapper> {
+        let name = self.context.get_remote_name(&file_wrapper.path)?;
+        Ok(FileWrapper {
+            name: name.unwrap_or_else(|| file_wrapper.name.clone()),
+            ..file_wrapper.clone()
+        })
+    }
+
+    /// Uploads carry the permissions of the local file when they are preserved, and none
+    /// otherwise, so whatever is stored on the remote is left alone.
+    fn with_local_permissions(&self, file_wrapper: &FileWrapper) -> Result<FileWrapper> {
+        let permissions =
+            if self.config.preserve_permissions && file_wrapper.symlink_target.is_none() {
+                permissions::read(&file_wrapper.path, self.config.preserve_owner)?
+            } else {
+                Permissions::default()
+            };
+        Ok(FileWrapper {
+            permissions,
+            ..file_wrapper.clone()
+        })
+    }
+
+    fn get_import_mime_type(&self, file_wrapper: &FileWrapper) -> Option<&String> {
+        if file_wrapper.symlink_target.is_some() {
+            return None;
+        }
+        let extension = file_wrapper.path.extension()?.to_str()?;
+        self.config.import.get(&extension.to_lowercase())
+    }
+
+    fn store_uploaded_file(
+        &self,
+        remote_file: &RemoteFile,
+        file_wrapper: &FileWrapper,
+    ) -> Result<()> {
         let mut response_file_wrapper =
-            Drive::convert_to_file_wrapper(&response.1, &file_wrapper.path);
-        response_file_wrapper.last_accessed = file_wrapper.last_accessed;
+            Drive::convert_to_file_wrapper(remote_file, &file_wrapper.path);
+        let metadata = if file_wrapper.symlink_target.is_some() {
+            fs::symlink_metadata(&file_wrapper.path)?
+        } else {
+            file_wrapper.path.metadata()?
+        };
+        response_file_wrapper.last_accessed = metadata.modified()?;
         self.context.store_file(&response_file_wrapper)?;
+        if file_wrapper.symlink_target.is_none() {
+            self.write_xattrs(&response_file_wrapper);
+        }
         debug!(
             "Uploaded and stored {} correctly",
             file_wrapper.path.display()
         );
+        self.emit(SyncEvent::Uploaded {
+            path: file_wrapper.path.clone(),
+        });
         Ok(())
     }
 
-    fn convert_to_file_wrapper(file: &File, path: &Path) -> FileWrapper {
-        FileWrapper {
-            id: file.id.clone().unwrap(),
-            name: file.name.clone().unwrap(),
-            mime_type: file.mime_type.clone().unwrap(),
-            path: path.to_path_buf(),
-            directory: file.mime_type.as_ref().unwrap() == DIRECTORY_MIME_TYPE,
-            web_view_link: file.web_view_link.clone(),
-            owned_by_me: file.owned_by_me.unwrap_or(true),
-            last_modified: file.modified_time.unwrap().into(),
-            last_accessed: SystemTime::UNIX_EPOCH,
-            trashed: file.trashed.unwrap_or(false),
-        }
-    }
-
-    fn convert_to_file(&self, file_wrapper: &FileWrapper) -> File {
-        let mime_type = if file_wrapper.directory {
-            Some(DIRECTORY_MIME_TYPE.to_string())
-        } else {
-            Some(file_wrapper.clone().mime_type)
-        };
-        let path_parent = file_wrapper.path.parent();
-        let parents = if let Some(path) = path_parent {
-            if path == self.config.root_dir {
-                None
-            } else {
-                Some(vec![path
-                    .file_name()
-                    .unwrap()
-                    .to_str()
-                    .unwrap()
-                    .to_string()])
-            }
-        } else {
-            None
+    /// Files are only uploaded into directories that are already on the remote, so a file is never
+    /// put somewhere other than where it is locally.
+    fn get_parent_id(&self, file_wrapper: &FileWrapper) -> Result<Option<String>> {
+        let path_parent = match file_wrapper.path.parent() {
+            Some(path_parent) if path_parent != self.config.root_dir => path_parent,
+            _ => return self.get_remote_folder_id(),
         };
-        File {
-            mime_type,
-            parents,
-            name: Some(file_wrapper.name.clone()),
-            ..Default::default()
+        match self.context.get_file_by_path(path_parent)? {
+            Some(parent) => Ok(Some(parent.id)),
+            None => Err(RdriveError::Path(
+                path_parent.to_path_buf(),
+                "Directory isn't on the remote".to_string(),
+            )),
         }
     }
 
-    fn get_config() -> Config {
-        let config_file = Drive::get_config_file();
-        let stored_config: serde_json::Result<StoredConfig> =
-            serde_json::from_reader(BufReader::new(&config_file));
-        if let Ok(config) = stored_config {
-            return Config {
-                exclude: config
-                    .exclude
-                    .iter()
-                    .map(|pattern| Pattern::new(pattern).unwrap())
-                    .collect(),
-                include: config
-                    .include
-                    .iter()
-                    .map(|pattern| Pattern::new(pattern).unwrap())
-                    .collect(),
-                root_dir: config.root_dir,
-            };
-        }
-        let default_root_dir = Path::new(&<Drive>::get_home_dir()).join("rdrive");
-        let default_stored_config = StoredConfig {
-            exclude: Vec::new(),
-            include: Vec::new(),
-            root_dir: default_root_dir.clone(),
+    /// The ID of the remote folder synced to the root directory, or `None` for the top of the
+    /// remote. Files are never uploaded to the top of the remote while a configured remote folder
+    /// hasn't been found.
+    fn get_remote_folder_id(&self) -> Result<Option<String>> {
+        let remote_folder = match &self.config.remote_folder {
+            Some(remote_folder) => remote_folder,
+            None => return Ok(None),
         };
-        let write_result =
-            serde_json::to_writer_pretty(BufWriter::new(&config_file), &default_stored_config);
-        if let Err(result) = write_result {
-            error!("{}", result);
-        }
-        Config {
-            exclude: Vec::new(),
-            include: Vec::new(),
-            root_dir: default_root_dir.clone(),
+        match self.remote_folder_id.borrow().clone() {
+            Some(remote_folder_id) => Ok(Some(remote_folder_id)),
+            None => Err(RdriveError::Config(format!(
+                "Remote folder {} hasn't been found yet",
+                remote_folder
+            ))),
         }
     }
 
-    fn get_home_dir() -> String {
-        match env::consts::OS {
-            "windows" => env::var("USERPROFILE").unwrap(),
-            _ => env::var("HOME").unwrap(),
+    fn convert_to_file_wrapper(file: &RemoteFile, path: &Path) -> FileWrapper {
+        FileWrapper {
+            id: file.id.clone(),
+            name: file.name.clone(),
+            mime_type: file.mime_type.clone(),
+            path: path.to_path_buf(),
+            directory: file.mime_type == DIRECTORY_MIME_TYPE,
+            web_view_link: file.web_view_link.clone(),
+            owned_by_me: file.owned_by_me,
+            last_modified: file.modified_time,
+            last_accessed: SystemTime::UNIX_EPOCH,
+            trashed: file.trashed,
+            version: file.version.clone(),
+            parents: file.parents.clone(),
+            can_edit: file.can_edit,
+            shortcut_target_id: None,
+            permissions: file.permissions.clone(),
+            symlink_target: file.symlink_target.clone(),
         }
     }
+}
 
-    fn get_config_file() -> fs::File {
-        let config_file = Path::new(&Drive::get_base_config_path())
-            .join("rdrive")
-            .join("config.json");
-        if !config_file.exists() {
-            let create_config_dir = create_dir_all(config_file.parent().unwrap());
-            if let Err(error) = create_config_dir {
-                panic!(
-                    "Failed to create config path {}. {}",
-                    config_file.display(),
-                    error
-                );
-            }
-            return fs::File::create(config_file).unwrap();
-        }
-        fs::OpenOptions::new()
-            .write(true)
-            .read(true)
-            .open(config_file)
-            .unwrap()
-    }
+/// Times are compared in whole seconds since the epoch, as not every file system or remote keeps
+/// them any more precisely. The epoch itself marks a file that has never been synced.
+pub(crate) fn as_secs(time: SystemTime) -> u64 {
+    time.duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
 
-    fn get_base_config_path() -> String {
-        match env::consts::OS {
-            "windows" => env::var("LOCALAPPDATA").unwrap(),
-            "linux" => {
-                env::var("XDG_CONFIG_HOME").unwrap_or(env::var("HOME").unwrap() + "/.config")
-            }
-            "macos" => env::var("HOME").unwrap() + "/Library/Preferences",
-            _ => String::new(),
-        }
-    }
+/// What a sync cycle does with a file that is already known.
+#[derive(Clone, Debug, Eq, PartialEq)]
+enum SyncAction {
+    Nothing,
+    Download,
+    Upload(Box<FileWrapper>),
+    /// The file was deleted locally, so is deleted from the remote.
+    DeleteRemote,
 }
 
-const DIRECTORY_MIME_TYPE: &str = "application/vnd.google-apps.folder";
+pub const DIRECTORY_MIME_TYPE: &str = "application/vnd.google-apps.folder";
+
+/// Where files shared with the user are synced to, unless they have been added to My Drive.
+pub const SHARED_WITH_ME_DIRECTORY: &str = "Shared with me";
 
 #[derive(Clone, Eq, PartialEq, Debug)]
 pub struct FileWrapper {
@@ -489,17 +1805,472 @@ pub struct FileWrapper {
     pub last_modified: DateTime<FixedOffset>,
     pub last_accessed: SystemTime,
     pub trashed: bool,
+    pub version: Option<String>,
+    pub parents: Vec<String>,
+    /// Whether the user can change the file on the remote. Files that can't be changed are
+    /// made read only locally.
+    pub can_edit: bool,
+    /// For shortcuts synced as symlinks, the ID of the file the symlink points to.
+    pub shortcut_target_id: Option<String>,
+    /// The POSIX permissions stored on the remote, or read from the local file before upload.
+    pub permissions: Permissions,
+    /// For symlinks stored on the remote, the path the symlink points to.
+    pub symlink_target: Option<String>,
 }
 
-#[derive(Default, Clone, Debug, Serialize, Deserialize)]
-struct StoredConfig {
-    exclude: Vec<String>,
-    include: Vec<String>,
-    root_dir: PathBuf,
+#[cfg(test)]
+impl FileWrapper {
+    /// An editable text file that has never been synced, for tests to override the fields they
+    /// care about.
+    pub(crate) fn test_default() -> FileWrapper {
+        FileWrapper {
+            id: "id".to_string(),
+            name: "name".to_string(),
+            mime_type: "text/plain".to_string(),
+            path: PathBuf::from("name"),
+            directory: false,
+            web_view_link: None,
+            owned_by_me: true,
+            last_modified: DateTime::parse_from_rfc3339("2024-01-01T00:00:00+00:00").unwrap(),
+            last_accessed: SystemTime::UNIX_EPOCH,
+            trashed: false,
+            version: None,
+            parents: Vec::new(),
+            can_edit: true,
+            shortcut_target_id: None,
+            permissions: Permissions::default(),
+            symlink_target: None,
+        }
+    }
 }
 
-struct Config {
-    exclude: Vec<Pattern>,
-    include: Vec<Pattern>,
-    root_dir: PathBuf,
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use rusqlite::Connection;
+
+    use super::*;
+    use crate::remote::fake::FakeRemote;
+
+    /// An empty directory to sync to, named after the test using it.
+    fn root_dir(name: &str) -> PathBuf {
+        let root_dir = env::temp_dir().join(name);
+        let _ = fs::remove_dir_all(&root_dir);
+        fs::create_dir_all(&root_dir).unwrap();
+        root_dir
+    }
+
+    fn get_drive(remote: &Rc<FakeRemote>, config: Config) -> Drive {
+        let context = DbContext::new(Connection::open_in_memory().unwrap());
+        context.init().unwrap();
+        let remote: Rc<dyn Remote> = remote.clone();
+        Drive::new(remote, context, config)
+    }
+
+    fn remote_file(id: &str, name: &str) -> RemoteFile {
+        RemoteFile {
+            id: id.to_string(),
+            name: name.to_string(),
+            ..RemoteFile::test_default()
+        }
+    }
+
+    /// Changes a synced file locally, as an edit made after the last sync.
+    fn edit(path: &Path, content: &str) {
+        Drive::set_read_only(path, false).unwrap();
+        fs::write(path, content).unwrap();
+        Drive::set_modified(path, SystemTime::now()).unwrap();
+    }
+
+    #[tokio::test]
+    async fn sync_should_abort_when_root_dir_is_missing_or_empty_after_syncing() {
+        let root_dir = root_dir("rdrive-drive-root-dir-test");
+        let remote = Rc::new(FakeRemote::new(vec![(remote_file("a", "a.txt"), b"a")]));
+        let drive = get_drive(&remote, Config::test_default(root_dir.clone()));
+        drive.sync().await.unwrap();
+        assert!(root_dir.join("a.txt").is_file());
+
+        fs::remove_file(root_dir.join("a.txt")).unwrap();
+        assert!(drive.sync().await.is_err());
+        fs::remove_dir_all(&root_dir).unwrap();
+        assert!(drive.sync().await.is_err());
+
+        assert!(!remote.was_called("trash"));
+        assert!(!remote.get("a").unwrap().trashed);
+        assert!(!root_dir.exists());
+    }
+
+    #[tokio::test]
+    async fn sync_should_never_download_in_upload_only_mode() {
+        let root_dir = root_dir("rdrive-drive-upload-only-test");
+        fs::write(root_dir.join("local.txt"), "local").unwrap();
+        let remote = Rc::new(FakeRemote::new(vec![
+            (remote_file("local", "local.txt"), b"remote"),
+            (remote_file("remote", "remote.txt"), b"remote"),
+        ]));
+        let config = Config {
+            mode: SyncMode::UploadOnly,
+            ..Config::test_default(root_dir.clone())
+        };
+        let drive = get_drive(&remote, config);
+        drive.sync().await.unwrap();
+
+        assert!(!remote.was_called("download"));
+        assert!(remote.was_called("update local"));
+        assert_eq!(remote.content("local").unwrap(), b"local");
+        assert!(!root_dir.join("remote.txt").exists());
+    }
+
+    #[tokio::test]
+    async fn sync_should_revert_local_changes_in_download_only_mode() {
+        let root_dir = root_dir("rdrive-drive-download-only-test");
+        let read_only = RemoteFile {
+            can_edit: false,
+            ..remote_file("read-only", "read-only.txt")
+        };
+        let remote = Rc::new(FakeRemote::new(vec![
+            (remote_file("editable", "editable.txt"), b"remote"),
+            (read_only, b"remote"),
+        ]));
+        let config = Config {
+            mode: SyncMode::DownloadOnly,
+            ..Config::test_default(root_dir.clone())
+        };
+        let drive = get_drive(&remote, config);
+        drive.sync().await.unwrap();
+        edit(&root_dir.join("editable.txt"), "local");
+        edit(&root_dir.join("read-only.txt"), "local");
+        fs::write(root_dir.join("new.txt"), "local").unwrap();
+        drive.sync().await.unwrap();
+
+        assert!(!remote.was_called("update"));
+        assert!(!remote.was_called("upload"));
+        assert_eq!(fs::read(root_dir.join("editable.txt")).unwrap(), b"remote");
+        assert_eq!(fs::read(root_dir.join("read-only.txt")).unwrap(), b"remote");
+        assert!(!root_dir.join("new.txt").exists());
+        let restore = drive
+            .restore_revision(&root_dir.join("editable.txt"), "1", true)
+            .await;
+        assert!(restore.is_err());
+        assert!(!remote.was_called("download_revision"));
+    }
+
+    fn directory(id: &str, name: &str, parents: &[&str]) -> RemoteFile {
+        RemoteFile {
+            mime_type: DIRECTORY_MIME_TYPE.to_string(),
+            parents: parents.iter().map(|parent| parent.to_string()).collect(),
+            ..remote_file(id, name)
+        }
+    }
+
+    fn remote_folder_files() -> Vec<(RemoteFile, &'static [u8])> {
+        vec![
+            (directory("work", "Work", &[]), b""),
+            (directory("projects", "Projects", &["work"]), b""),
+            (
+                RemoteFile {
+                    parents: vec!["projects".to_string()],
+                    ..remote_file("inside", "inside.txt")
+                },
+                b"inside",
+            ),
+            (remote_file("outside", "outside.txt"), b"outside"),
+        ]
+    }
+
+    #[tokio::test]
+    async fn sync_should_only_sync_remote_folder_found_by_path_or_id() {
+        for remote_folder in ["Work/Projects", "/Work/Projects/", "projects"] {
+            let root_dir = root_dir("rdrive-drive-remote-folder-test");
+            fs::write(root_dir.join("new.txt"), "new").unwrap();
+            let remote = Rc::new(FakeRemote::new(remote_folder_files()));
+            let config = Config {
+                remote_folder: Some(remote_folder.to_string()),
+                ..Config::test_default(root_dir.clone())
+            };
+            let drive = get_drive(&remote, config);
+            drive.sync().await.unwrap();
+
+            assert_eq!(fs::read(root_dir.join("inside.txt")).unwrap(), b"inside");
+            assert!(!root_dir.join("outside.txt").exists());
+            assert!(!root_dir.join("Work").exists());
+            let uploaded = remote.get("uploaded-4").unwrap();
+            assert_eq!(uploaded.name, "new.txt");
+            assert_eq!(uploaded.parents, vec!["projects".to_string()]);
+        }
+    }
+
+    #[tokio::test]
+    async fn sync_should_abort_when_remote_folder_is_not_found() {
+        for remote_folder in ["Work/Missing", "Projects", "inside"] {
+            let root_dir = root_dir("rdrive-drive-missing-remote-folder-test");
+            fs::write(root_dir.join("new.txt"), "new").unwrap();
+            let remote = Rc::new(FakeRemote::new(remote_folder_files()));
+            let config = Config {
+                remote_folder: Some(remote_folder.to_string()),
+                ..Config::test_default(root_dir.clone())
+            };
+            let drive = get_drive(&remote, config);
+
+            assert!(matches!(drive.sync().await, Err(RdriveError::Config(_))));
+            assert!(!remote.was_called("upload"));
+            assert!(!root_dir.join("outside.txt").exists());
+        }
+    }
+
+    fn get_local_names(
+        case_insensitive: bool,
+        files: &[RemoteFile],
+        stored_suffixes: &HashMap<String, usize>,
+    ) -> (HashMap<String, String>, HashMap<String, usize>) {
+        let root_dir = env::temp_dir().join("rdrive-drive-local-names-test");
+        let drive = get_drive(&Rc::default(), Config::test_default(root_dir));
+        drive.case_insensitive.set(case_insensitive).unwrap();
+        let files_by_id = files
+            .iter()
+            .map(|file| (file.id.clone(), file.clone()))
+            .collect();
+        drive.get_local_names(files, &files_by_id, &HashMap::new(), stored_suffixes)
+    }
+
+    #[test]
+    fn get_local_names_should_suffix_duplicates_in_order_of_id() {
+        let files = vec![
+            remote_file("b", "a.txt"),
+            remote_file("a", "a.txt"),
+            remote_file("c", "a.txt"),
+            remote_file("taken", "a (1).txt"),
+            directory("dir", "dir", &[]),
+            RemoteFile {
+                parents: vec!["dir".to_string()],
+                ..remote_file("nested", "a.txt")
+            },
+        ];
+        let (local_names, suffixes) = get_local_names(false, &files, &HashMap::new());
+
+        assert_eq!(local_names["a"], "a.txt");
+        assert_eq!(local_names["b"], "a (2).txt");
+        assert_eq!(local_names["c"], "a (3).txt");
+        assert_eq!(local_names["taken"], "a (1).txt");
+        assert_eq!(local_names["nested"], "a.txt");
+        assert_eq!(suffixes["b"], 2);
+    }
+
+    #[test]
+    fn get_local_names_should_keep_suffixes_between_cycles() {
+        let a = remote_file("a", "a.txt");
+        let b = remote_file("b", "a.txt");
+        let (_, suffixes) = get_local_names(false, &[a.clone(), b.clone()], &HashMap::new());
+        let first = remote_file("0", "a.txt");
+        let (local_names, suffixes) =
+            get_local_names(false, &[first.clone(), a, b.clone()], &suffixes);
+
+        assert_eq!(local_names["a"], "a.txt");
+        assert_eq!(local_names["b"], "a (1).txt");
+        assert_eq!(local_names["0"], "a (2).txt");
+
+        let (local_names, _) = get_local_names(false, &[first, b], &suffixes);
+        assert_eq!(local_names["b"], "a (1).txt");
+        assert_eq!(local_names["0"], "a (2).txt");
+    }
+
+    #[test]
+    fn get_local_names_should_only_suffix_names_differing_by_case_if_case_insensitive() {
+        let files = vec![
+            remote_file("1", "Report.txt"),
+            remote_file("2", "report.txt"),
+        ];
+        let (local_names, _) = get_local_names(true, &files, &HashMap::new());
+        assert_eq!(local_names["1"], "Report.txt");
+        assert_eq!(local_names["2"], "report (1).txt");
+
+        let (local_names, suffixes) = get_local_names(false, &files, &HashMap::new());
+        assert_eq!(local_names["1"], "Report.txt");
+        assert_eq!(local_names["2"], "report.txt");
+        assert_eq!(suffixes["2"], 0);
+    }
+
+    fn shortcut(id: &str, name: &str, target_id: &str) -> RemoteFile {
+        RemoteFile {
+            mime_type: "application/vnd.google-apps.shortcut".to_string(),
+            shortcut_target_id: Some(target_id.to_string()),
+            ..remote_file(id, name)
+        }
+    }
+
+    #[test]
+    fn get_shortcut_target_id_should_ignore_loops_and_missing_targets() {
+        let files = vec![
+            remote_file("file", "file.txt"),
+            shortcut("chain", "chain", "to-file"),
+            shortcut("to-file", "to-file", "file"),
+            shortcut("loop-a", "loop-a", "loop-b"),
+            shortcut("loop-b", "loop-b", "loop-a"),
+            shortcut("self", "self", "self"),
+            shortcut("missing", "missing", "gone"),
+            shortcut("trashed", "trashed", "trashed-file"),
+            RemoteFile {
+                trashed: true,
+                ..remote_file("trashed-file", "trashed.txt")
+            },
+        ];
+        let files_by_id: HashMap<String, RemoteFile> = files
+            .iter()
+            .map(|file| (file.id.clone(), file.clone()))
+            .collect();
+        let root_dir = env::temp_dir().join("rdrive-drive-shortcut-test");
+        let drive = get_drive(&Rc::default(), Config::test_default(root_dir));
+        let get_target_id = |id: &str| drive.get_shortcut_target_id(&files_by_id[id], &files_by_id);
+
+        assert_eq!(get_target_id("chain"), Some("file".to_string()));
+        assert_eq!(get_target_id("loop-a"), None);
+        assert_eq!(get_target_id("loop-b"), None);
+        assert_eq!(get_target_id("self"), None);
+        assert_eq!(get_target_id("missing"), None);
+        assert_eq!(get_target_id("trashed"), None);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn sync_should_recreate_shortcut_when_its_target_moves() {
+        let root_dir = root_dir("rdrive-drive-moved-shortcut-target-test");
+        let file = RemoteFile {
+            parents: vec!["old".to_string()],
+            ..remote_file("file", "file.txt")
+        };
+        let remote = Rc::new(FakeRemote::new(vec![
+            (directory("old", "old", &[]), b""),
+            (directory("new", "new", &[]), b""),
+            (file.clone(), b"file"),
+            (shortcut("shortcut", "shortcut", "file"), b""),
+        ]));
+        let drive = get_drive(&remote, Config::test_default(root_dir.clone()));
+        drive.sync().await.unwrap();
+        let shortcut_path = root_dir.join("shortcut");
+        assert_eq!(
+            fs::read_link(&shortcut_path).unwrap(),
+            PathBuf::from("old/file.txt")
+        );
+
+        let moved_file = RemoteFile {
+            parents: vec!["new".to_string()],
+            ..file
+        };
+        remote.put(moved_file, b"file");
+        drive.sync().await.unwrap();
+
+        assert_eq!(
+            fs::read_link(&shortcut_path).unwrap(),
+            PathBuf::from("new/file.txt")
+        );
+        assert_eq!(fs::read(&shortcut_path).unwrap(), b"file");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn get_local_files_should_not_follow_symlinks_back_to_a_parent() {
+        let root_dir = root_dir("rdrive-drive-symlink-loop-test");
+        fs::create_dir_all(root_dir.join("a/b")).unwrap();
+        fs::write(root_dir.join("a/file.txt"), "file").unwrap();
+        std::os::unix::fs::symlink("..", root_dir.join("a/b/to-a")).unwrap();
+        std::os::unix::fs::symlink("../..", root_dir.join("a/b/to-root")).unwrap();
+        let config = Config {
+            symlinks: SymlinkPolicy::Follow,
+            ..Config::test_default(root_dir.clone())
+        };
+        let drive = get_drive(&Rc::default(), config);
+
+        let mut paths: Vec<PathBuf> = drive
+            .get_local_files()
+            .unwrap()
+            .into_iter()
+            .map(|file| file.path.strip_prefix(&root_dir).unwrap().to_path_buf())
+            .collect();
+        paths.sort();
+        let expected: Vec<PathBuf> = ["a", "a/b", "a/b/to-a", "a/b/to-root", "a/file.txt"]
+            .iter()
+            .map(PathBuf::from)
+            .collect();
+        assert_eq!(paths, expected);
+    }
+
+    #[tokio::test]
+    async fn sync_should_keep_modified_times_on_download_and_upload() {
+        let root_dir = root_dir("rdrive-drive-mtime-test");
+        let file = remote_file("a", "a.txt");
+        let remote = Rc::new(FakeRemote::new(vec![(file.clone(), b"remote")]));
+        let drive = get_drive(&remote, Config::test_default(root_dir.clone()));
+        drive.sync().await.unwrap();
+        let path = root_dir.join("a.txt");
+        let downloaded_modified = fs::metadata(&path).unwrap().modified().unwrap();
+        assert_eq!(downloaded_modified, SystemTime::from(file.modified_time));
+
+        let local_modified = SystemTime::from(file.modified_time) + Duration::from_secs(60 * 60);
+        fs::write(&path, "local").unwrap();
+        Drive::set_modified(&path, local_modified).unwrap();
+        drive.sync().await.unwrap();
+        assert!(remote.was_called("update a"));
+        assert_eq!(
+            SystemTime::from(remote.get("a").unwrap().modified_time),
+            local_modified
+        );
+
+        let calls = remote.calls().len();
+        drive.sync().await.unwrap();
+        assert_eq!(remote.calls().len(), calls);
+        assert_eq!(
+            fs::metadata(&path).unwrap().modified().unwrap(),
+            local_modified
+        );
+    }
+
+    #[tokio::test]
+    async fn create_file_should_leave_local_copy_alone_when_download_fails() {
+        let root_dir = root_dir("rdrive-drive-failed-download-test");
+        let remote = Rc::new(FakeRemote::new(vec![(
+            remote_file("a", "a.txt"),
+            b"remote",
+        )]));
+        let drive = get_drive(&remote, Config::test_default(root_dir.clone()));
+        drive.sync().await.unwrap();
+        edit(&root_dir.join("a.txt"), "local");
+        let changed = RemoteFile {
+            version: Some("2".to_string()),
+            ..remote_file("a", "a.txt")
+        };
+        remote.put(changed, b"changed");
+        remote.remove_content("a");
+        drive.sync().await.unwrap();
+
+        assert!(remote.was_called("download a"));
+        assert_eq!(fs::read(root_dir.join("a.txt")).unwrap(), b"local");
+        assert!(trash::list(&root_dir).unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn sync_should_only_delete_remote_files_in_upload_only_mode_if_allowed() {
+        for delete_remote in [false, true] {
+            let root_dir = root_dir("rdrive-drive-delete-remote-test");
+            let remote = Rc::new(FakeRemote::new(vec![(remote_file("a", "a.txt"), b"a")]));
+            let two_way_drive = get_drive(&remote, Config::test_default(root_dir.clone()));
+            two_way_drive.sync().await.unwrap();
+            fs::write(root_dir.join("kept.txt"), "kept").unwrap();
+            let config = Config {
+                mode: SyncMode::UploadOnly,
+                delete_remote,
+                ..Config::test_default(root_dir.clone())
+            };
+            let drive = Drive {
+                config,
+                ..two_way_drive
+            };
+            fs::remove_file(root_dir.join("a.txt")).unwrap();
+            drive.sync().await.unwrap();
+
+            assert_eq!(remote.was_called("trash a"), delete_remote);
+            assert_eq!(remote.get("a").unwrap().trashed, delete_remote);
+            assert!(!root_dir.join("a.txt").exists());
+        }
+    }
 }
@@ -0,0 +1,103 @@
+use std::path::PathBuf;
+
+use drive3::hyper::http;
+use drive3::hyper_util::client::legacy;
+use thiserror::Error;
+
+/// Every error rdrive can produce, grouped by where it came from.
+#[derive(Debug, Error)]
+pub enum RdriveError {
+    #[error("Configuration error: {0}")]
+    Config(String),
+    #[error("Authentication error: {0}")]
+    Auth(String),
+    #[error("Remote API error: {0}")]
+    Api(String),
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Database error: {0}")]
+    Db(rusqlite::Error),
+    #[error("Database schema version {found} is newer than version {supported} supported by this version of rdrive")]
+    DbVersion { found: i32, supported: i32 },
+    #[error("Invalid path {path}: {reason}", path = .0.display(), reason = .1)]
+    Path(PathBuf, String),
+    #[error("Invalid arguments: {0}")]
+    Usage(String),
+}
+
+pub type Result<T> = std::result::Result<T, RdriveError>;
+
+impl From<rusqlite::Error> for RdriveError {
+    /// Paths that can't be stored are reported as path errors rather than database errors.
+    fn from(error: rusqlite::Error) -> Self {
+        match error {
+            rusqlite::Error::ToSqlConversionFailure(source) => {
+                match source.downcast::<RdriveError>() {
+                    Ok(source) => *source,
+                    Err(source) => RdriveError::Db(rusqlite::Error::ToSqlConversionFailure(source)),
+                }
+            }
+            error => RdriveError::Db(error),
+        }
+    }
+}
+
+impl From<drive3::Error> for RdriveError {
+    fn from(error: drive3::Error) -> Self {
+        RdriveError::Api(error.to_string())
+    }
+}
+
+impl From<http::Error> for RdriveError {
+    fn from(error: http::Error) -> Self {
+        RdriveError::Api(error.to_string())
+    }
+}
+
+impl From<http::uri::InvalidUri> for RdriveError {
+    fn from(error: http::uri::InvalidUri) -> Self {
+        RdriveError::Config(error.to_string())
+    }
+}
+
+impl From<legacy::Error> for RdriveError {
+    fn from(error: legacy::Error) -> Self {
+        RdriveError::Api(error.to_string())
+    }
+}
+
+impl From<roxmltree::Error> for RdriveError {
+    fn from(error: roxmltree::Error) -> Self {
+        RdriveError::Api(error.to_string())
+    }
+}
+
+impl From<chrono::ParseError> for RdriveError {
+    fn from(error: chrono::ParseError) -> Self {
+        RdriveError::Api(error.to_string())
+    }
+}
+
+impl From<std::str::Utf8Error> for RdriveError {
+    fn from(error: std::str::Utf8Error) -> Self {
+        RdriveError::Api(error.to_string())
+    }
+}
+
+impl From<std::string::FromUtf8Error> for RdriveError {
+    fn from(error: std::string::FromUtf8Error) -> Self {
+        RdriveError::Api(error.to_string())
+    }
+}
+
+impl From<mime::FromStrError> for RdriveError {
+    fn from(error: mime::FromStrError) -> Self {
+        RdriveError::Api(error.to_string())
+    }
+}
+
+impl From<http::method::InvalidMethod> for RdriveError {
+    fn from(error: http::method::InvalidMethod) -> Self {
+        RdriveError::Api(error.to_string())
+    }
+}
@@ -0,0 +1,12 @@
+use std::path::PathBuf;
+
+/// Events emitted by the sync engine so that embedding applications can follow its progress.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub enum SyncEvent {
+    Downloaded { path: PathBuf },
+    Uploaded { path: PathBuf },
+    Failed { path: PathBuf, error: String },
+    CycleCompleted,
+}
+
+pub type SyncListener = Box<dyn Fn(&SyncEvent)>;
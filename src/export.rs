@@ -0,0 +1,139 @@
+use std::collections::BTreeMap;
+
+pub const GOOGLE_DOCUMENT_MIME_TYPE: &str = "application/vnd.google-apps.document";
+pub const GOOGLE_SPREADSHEET_MIME_TYPE: &str = "application/vnd.google-apps.spreadsheet";
+pub const GOOGLE_PRESENTATION_MIME_TYPE: &str = "application/vnd.google-apps.presentation";
+pub const GOOGLE_DRAWING_MIME_TYPE: &str = "application/vnd.google-apps.drawing";
+
+/// A format a Google-native file can be exported to, named by the extension given to the
+/// exported copy.
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+pub struct ExportFormat {
+    pub extension: &'static str,
+    pub mime_type: &'static str,
+}
+
+const DOCX: ExportFormat = ExportFormat {
+    extension: "docx",
+    mime_type: "application/vnd.openxmlformats-officedocument.wordprocessingml.document",
+};
+const ODT: ExportFormat = ExportFormat {
+    extension: "odt",
+    mime_type: "application/vnd.oasis.opendocument.text",
+};
+const RTF: ExportFormat = ExportFormat {
+    extension: "rtf",
+    mime_type: "application/rtf",
+};
+const EPUB: ExportFormat = ExportFormat {
+    extension: "epub",
+    mime_type: "application/epub+zip",
+};
+const MD: ExportFormat = ExportFormat {
+    extension: "md",
+    mime_type: "text/markdown",
+};
+const TXT: ExportFormat = ExportFormat {
+    extension: "txt",
+    mime_type: "text/plain",
+};
+const XLSX: ExportFormat = ExportFormat {
+    extension: "xlsx",
+    mime_type: "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet",
+};
+const ODS: ExportFormat = ExportFormat {
+    extension: "ods",
+    mime_type: "application/vnd.oasis.opendocument.spreadsheet",
+};
+const CSV: ExportFormat = ExportFormat {
+    extension: "csv",
+    mime_type: "text/csv",
+};
+const TSV: ExportFormat = ExportFormat {
+    extension: "tsv",
+    mime_type: "text/tab-separated-values",
+};
+const PPTX: ExportFormat = ExportFormat {
+    extension: "pptx",
+    mime_type: "application/vnd.openxmlformats-officedocument.presentationml.presentation",
+};
+const ODP: ExportFormat = ExportFormat {
+    extension: "odp",
+    mime_type: "application/vnd.oasis.opendocument.presentation",
+};
+const SVG: ExportFormat = ExportFormat {
+    extension: "svg",
+    mime_type: "image/svg+xml",
+};
+const PNG: ExportFormat = ExportFormat {
+    extension: "png",
+    mime_type: "image/png",
+};
+const JPG: ExportFormat = ExportFormat {
+    extension: "jpg",
+    mime_type: "image/jpeg",
+};
+const PDF: ExportFormat = ExportFormat {
+    extension: "pdf",
+    mime_type: "application/pdf",
+};
+
+/// The formats the Drive export endpoint supports for each Google-native MIME type.
+const EXPORT_FORMATS: &[(&str, &[ExportFormat])] = &[
+    (
+        GOOGLE_DOCUMENT_MIME_TYPE,
+        &[DOCX, ODT, PDF, RTF, EPUB, MD, TXT],
+    ),
+    (GOOGLE_SPREADSHEET_MIME_TYPE, &[XLSX, ODS, PDF, CSV, TSV]),
+    (GOOGLE_PRESENTATION_MIME_TYPE, &[PPTX, ODP, PDF, TXT]),
+    (GOOGLE_DRAWING_MIME_TYPE, &[SVG, PNG, JPG, PDF]),
+];
+
+/// The formats that can be converted to each Google-native MIME type on upload. Only formats
+/// that can also be exported are included, so changes made in Drive can be written back.
+const IMPORT_FORMATS: &[(&str, &[ExportFormat])] = &[
+    (GOOGLE_DOCUMENT_MIME_TYPE, &[DOCX, ODT, RTF, MD, TXT]),
+    (GOOGLE_SPREADSHEET_MIME_TYPE, &[XLSX, ODS, CSV, TSV]),
+    (GOOGLE_PRESENTATION_MIME_TYPE, &[PPTX, ODP]),
+];
+
+pub fn find_export_format(google_mime_type: &str, extension: &str) -> Option<ExportFormat> {
+    find_format(EXPORT_FORMATS, google_mime_type, extension)
+}
+
+pub fn find_import_format(google_mime_type: &str, extension: &str) -> Option<ExportFormat> {
+    find_format(IMPORT_FORMATS, google_mime_type, extension)
+}
+
+fn find_format(
+    formats_by_mime_type: &[(&str, &[ExportFormat])],
+    google_mime_type: &str,
+    extension: &str,
+) -> Option<ExportFormat> {
+    formats_by_mime_type
+        .iter()
+        .find(|(mime_type, _)| *mime_type == google_mime_type)
+        .and_then(|(_, formats)| {
+            formats
+                .iter()
+                .find(|format| format.extension.eq_ignore_ascii_case(extension))
+                .copied()
+        })
+}
+
+pub fn default_export() -> BTreeMap<String, String> {
+    BTreeMap::from([
+        (
+            GOOGLE_DOCUMENT_MIME_TYPE.to_string(),
+            DOCX.extension.to_string(),
+        ),
+        (
+            GOOGLE_SPREADSHEET_MIME_TYPE.to_string(),
+            XLSX.extension.to_string(),
+        ),
+        (
+            GOOGLE_PRESENTATION_MIME_TYPE.to_string(),
+            PPTX.extension.to_string(),
+        ),
+    ])
+}
@@ -0,0 +1,33 @@
+//! Sync files between a local directory and a remote such as Google Drive or WebDAV.
+//!
+//! The `rdrive` binary is a thin wrapper around [`SyncEngine`], which can also be embedded in
+//! other applications alongside the [`StateStore`] it keeps its index in.
+
+extern crate google_drive3 as drive3;
+
+mod check;
+mod checksum;
+mod config;
+mod conflict;
+mod dbcontext;
+mod drive;
+mod error;
+mod events;
+mod export;
+mod link;
+mod migrations;
+mod naming;
+mod permissions;
+mod remote;
+mod trash;
+mod xattr;
+
+pub use crate::check::Issue;
+pub use crate::config::{get_config, Config, ConflictPolicy, RemoteConfig, SyncMode};
+pub use crate::dbcontext::DbContext as StateStore;
+pub use crate::drive::{Drive as SyncEngine, FileWrapper};
+pub use crate::error::{RdriveError, Result};
+pub use crate::events::SyncEvent;
+pub use crate::permissions::Permissions;
+pub use crate::remote::{create_remote, Remote, RemoteFile, Revision};
+pub use crate::trash::TrashEntry;
@@ -0,0 +1,250 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::drive::{FileWrapper, DIRECTORY_MIME_TYPE};
+use crate::error::{RdriveError, Result};
+use crate::export::{
+    GOOGLE_DOCUMENT_MIME_TYPE, GOOGLE_DRAWING_MIME_TYPE, GOOGLE_PRESENTATION_MIME_TYPE,
+    GOOGLE_SPREADSHEET_MIME_TYPE,
+};
+
+const GOOGLE_MIME_TYPE_PREFIX: &str = "application/vnd.google-apps.";
+const ID_KEY: &str = "X-Rdrive-Id=";
+const LEGACY_SCRIPT_PREFIX: &str = "#!/usr/bin/env bash\nxdg-open ";
+const MAX_LINK_SIZE: u64 = 4096;
+
+/// How Google-native files that aren't exported are linked to from the local directory.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LinkFormat {
+    /// A freedesktop.org `Type=Link` desktop entry, opened by Linux file managers.
+    #[default]
+    Desktop,
+    /// An Internet Shortcut, understood by Windows, macOS and most Linux desktops.
+    Url,
+}
+
+impl LinkFormat {
+    fn extension(self) -> &'static str {
+        match self {
+            LinkFormat::Desktop => "desktop",
+            LinkFormat::Url => "url",
+        }
+    }
+}
+
+/// Whether the file only exists as a document in Google's editors, so has no content to download.
+pub fn is_google_native(mime_type: &str) -> bool {
+    mime_type.starts_with(GOOGLE_MIME_TYPE_PREFIX) && mime_type != DIRECTORY_MIME_TYPE
+}
+
+/// The extension of the link to a Google-native file, such as `gdoc.desktop`, so the kind of
+/// document is still visible once the link extension is hidden by a file manager.
+pub fn get_link_extension(mime_type: &str, link_format: LinkFormat) -> String {
+    format!(
+        "{}.{}",
+        get_google_extension(mime_type),
+        link_format.extension()
+    )
+}
+
+fn get_google_extension(mime_type: &str) -> &'static str {
+    match mime_type {
+        GOOGLE_DOCUMENT_MIME_TYPE => "gdoc",
+        GOOGLE_SPREADSHEET_MIME_TYPE => "gsheet",
+        GOOGLE_PRESENTATION_MIME_TYPE => "gslides",
+        GOOGLE_DRAWING_MIME_TYPE => "gdraw",
+        "application/vnd.google-apps.form" => "gform",
+        "application/vnd.google-apps.map" => "gmap",
+        "application/vnd.google-apps.site" => "gsite",
+        "application/vnd.google-apps.script" => "gscript",
+        "application/vnd.google-apps.jam" => "gjam",
+        _ => "glink",
+    }
+}
+
+fn get_icon(mime_type: &str) -> &'static str {
+    match mime_type {
+        GOOGLE_DOCUMENT_MIME_TYPE => "x-office-document",
+        GOOGLE_SPREADSHEET_MIME_TYPE => "x-office-spreadsheet",
+        GOOGLE_PRESENTATION_MIME_TYPE => "x-office-presentation",
+        GOOGLE_DRAWING_MIME_TYPE => "x-office-drawing",
+        _ => "text-html",
+    }
+}
+
+pub fn get_link_content(file_wrapper: &FileWrapper, link_format: LinkFormat) -> Result<String> {
+    let web_view_link = file_wrapper
+        .web_view_link
+        .as_ref()
+        .ok_or_else(|| RdriveError::Api(format!("{} has no web view link", file_wrapper.id)))?;
+    Ok(match link_format {
+        LinkFormat::Desktop => format!(
+            "[Desktop Entry]\nType=Link\nName={}\nURL={}\nIcon={}\n{}{}\n",
+            file_wrapper.name.replace(['\n', '\r'], " "),
+            web_view_link,
+            get_icon(&file_wrapper.mime_type),
+            ID_KEY,
+            file_wrapper.id
+        ),
+        LinkFormat::Url => format!(
+            "[InternetShortcut]\nURL={}\n{}{}\n",
+            web_view_link, ID_KEY, file_wrapper.id
+        ),
+    })
+}
+
+/// Reads the ID of the Google file a link written by [`get_link_content`] points to.
+pub fn read_link_id(content: &str) -> Option<&str> {
+    content
+        .lines()
+        .find_map(|line| line.trim().strip_prefix(ID_KEY))
+        .filter(|id| !id.is_empty())
+}
+
+/// Whether `path` is a link written by rdrive, including the `xdg-open` scripts written by
+/// versions before link formats were configurable.
+pub fn is_link_file(path: &Path) -> bool {
+    let extension = path.extension().unwrap_or_default();
+    let is_link =
+        extension == LinkFormat::Desktop.extension() || extension == LinkFormat::Url.extension();
+    match fs::metadata(path) {
+        Ok(metadata) if metadata.is_file() && metadata.len() <= MAX_LINK_SIZE => {}
+        _ => return false,
+    }
+    match fs::read_to_string(path) {
+        Ok(content) if is_link => read_link_id(&content).is_some(),
+        Ok(content) => content.starts_with(LEGACY_SCRIPT_PREFIX),
+        Err(_) => false,
+    }
+}
+
+/// The path of `to` relative to the directory `from_dir`, for symlinks that keep working when
+/// the root directory is moved.
+pub fn get_relative_path(from_dir: &Path, to: &Path) -> PathBuf {
+    let from_components: Vec<_> = from_dir.components().collect();
+    let to_components: Vec<_> = to.components().collect();
+    let common = from_components
+        .iter()
+        .zip(&to_components)
+        .take_while(|(from, to)| from == to)
+        .count();
+    let mut relative_path = PathBuf::new();
+    for _ in common..from_components.len() {
+        relative_path.push("..");
+    }
+    for component in &to_components[common..] {
+        relative_path.push(component);
+    }
+    relative_path
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use super::*;
+
+    fn get_file_wrapper(mime_type: &str) -> FileWrapper {
+        FileWrapper {
+            id: "1a2b3c".to_string(),
+            name: "Report".to_string(),
+            mime_type: mime_type.to_string(),
+            path: PathBuf::from("Report"),
+            web_view_link: Some("https://docs.google.com/document/d/1a2b3c/edit".to_string()),
+            ..FileWrapper::test_default()
+        }
+    }
+
+    #[test]
+    fn get_link_extension_should_add_google_and_link_extensions() {
+        let extension = get_link_extension(GOOGLE_SPREADSHEET_MIME_TYPE, LinkFormat::Desktop);
+        assert_eq!(extension, "gsheet.desktop");
+        let extension = get_link_extension(GOOGLE_PRESENTATION_MIME_TYPE, LinkFormat::Url);
+        assert_eq!(extension, "gslides.url");
+    }
+
+    #[test]
+    fn get_link_content_should_create_desktop_entry() {
+        let content = get_link_content(
+            &get_file_wrapper(GOOGLE_DOCUMENT_MIME_TYPE),
+            LinkFormat::Desktop,
+        );
+        assert_eq!(
+            content.unwrap(),
+            "[Desktop Entry]\nType=Link\nName=Report\nURL=https://docs.google.com/document/d/1a2b3c/edit\nIcon=x-office-document\nX-Rdrive-Id=1a2b3c\n"
+        );
+    }
+
+    #[test]
+    fn get_link_content_should_create_internet_shortcut() {
+        let content = get_link_content(
+            &get_file_wrapper(GOOGLE_DOCUMENT_MIME_TYPE),
+            LinkFormat::Url,
+        );
+        assert_eq!(
+            content.unwrap(),
+            "[InternetShortcut]\nURL=https://docs.google.com/document/d/1a2b3c/edit\nX-Rdrive-Id=1a2b3c\n"
+        );
+    }
+
+    #[test]
+    fn read_link_id_should_read_id_from_link_content() {
+        for link_format in [LinkFormat::Desktop, LinkFormat::Url] {
+            let content =
+                get_link_content(&get_file_wrapper(GOOGLE_DRAWING_MIME_TYPE), link_format).unwrap();
+            assert_eq!(read_link_id(&content), Some("1a2b3c"));
+        }
+        assert_eq!(
+            read_link_id("[InternetShortcut]\nURL=https://example.com\n"),
+            None
+        );
+    }
+
+    #[test]
+    fn is_link_file_should_only_match_links_written_by_rdrive() {
+        let content = get_link_content(
+            &get_file_wrapper(GOOGLE_DOCUMENT_MIME_TYPE),
+            LinkFormat::Desktop,
+        )
+        .unwrap();
+        let link_path = env::temp_dir().join("rdrive-link-test.gdoc.desktop");
+        fs::write(&link_path, &content).unwrap();
+        let text_path = env::temp_dir().join("rdrive-link-test.txt");
+        fs::write(&text_path, &content).unwrap();
+        let legacy_path = env::temp_dir().join("rdrive-link-test");
+        fs::write(
+            &legacy_path,
+            "#!/usr/bin/env bash\nxdg-open https://docs.google.com/document/d/1a2b3c/edit",
+        )
+        .unwrap();
+
+        assert!(is_link_file(&link_path));
+        assert!(!is_link_file(&text_path));
+        assert!(is_link_file(&legacy_path));
+        fs::remove_file(link_path).unwrap();
+        fs::remove_file(text_path).unwrap();
+        fs::remove_file(legacy_path).unwrap();
+    }
+
+    #[test]
+    fn get_relative_path_should_walk_up_to_common_directory() {
+        assert_eq!(
+            get_relative_path(Path::new("/rdrive/a/b"), Path::new("/rdrive/c/report.pdf")),
+            PathBuf::from("../../c/report.pdf")
+        );
+        assert_eq!(
+            get_relative_path(Path::new("/rdrive"), Path::new("/rdrive/c")),
+            PathBuf::from("c")
+        );
+    }
+
+    #[test]
+    fn is_google_native_should_not_include_folders() {
+        assert!(is_google_native(GOOGLE_DOCUMENT_MIME_TYPE));
+        assert!(!is_google_native(DIRECTORY_MIME_TYPE));
+        assert!(!is_google_native("application/pdf"));
+    }
+}
@@ -1,149 +1,191 @@
-extern crate google_drive3 as drive3;
+use std::env;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::time::Duration;
 
-use std::error::Error;
-use std::path::PathBuf;
-use std::time::{Duration, SystemTime};
-use std::{env, fs, thread};
-
-use drive3::common::Body;
-use drive3::hyper_rustls::HttpsConnector;
-use drive3::hyper_util::client::legacy::connect::HttpConnector;
-use drive3::hyper_util::client::legacy::Client;
-use drive3::yup_oauth2::CustomHyperClientBuilder;
-use drive3::{hyper_rustls, hyper_util, yup_oauth2, DriveHub};
-use log::{debug, error, LevelFilter, SetLoggerError};
+use log::LevelFilter;
 use log4rs::append::console::ConsoleAppender;
 use log4rs::append::file::FileAppender;
 use log4rs::config::{Appender, Config, Logger, Root};
 use log4rs::encode::pattern::PatternEncoder;
 use log4rs::filter::threshold::ThresholdFilter;
 use log4rs::Handle;
-use rusqlite::Connection;
-use yup_oauth2::authenticator::Authenticator;
-use yup_oauth2::{ApplicationSecret, InstalledFlowAuthenticator, InstalledFlowReturnMethod};
-
-use crate::drive::{Drive, FileWrapper};
-
-mod dbcontext;
-mod drive;
+use rdrive::{create_remote, get_config, RdriveError, Remote, Result, StateStore, SyncEngine};
 
 #[tokio::main]
-async fn main() -> Result<(), Box<dyn Error>> {
-    let _handle = configure_logging().unwrap();
-    let connection = get_db_connection();
-    let hub = DriveHub::new(get_client(), get_authenticator().await);
-
-    let drive = Drive::new(hub, connection);
-    drive.init().await;
+async fn main() {
+    if let Err(error) = run().await {
+        eprintln!("{}", error);
+        std::process::exit(1);
+    }
+}
 
-    loop {
-        drive.store_fetched_files().await?;
-        let existing_file_wrappers = drive.get_all_files(true).unwrap();
-        debug!("Retrieved {} files", existing_file_wrappers.len());
-        for file_wrapper in &existing_file_wrappers {
-            handle_existing_file(&drive, file_wrapper).await
+async fn run() -> Result<()> {
+    let _handle = configure_logging()?;
+    let config = get_config()?;
+
+    let remote: Rc<dyn Remote> = create_remote(&config.remote).await?.into();
+    let mut drives = Vec::new();
+    for pair_config in config.into_pairs() {
+        let state_store = match &pair_config.name {
+            Some(name) => StateStore::open_pair(name)?,
+            None => StateStore::open()?,
+        };
+        drives.push(SyncEngine::new(remote.clone(), state_store, pair_config));
+    }
+    match env::args().nth(1).as_deref() {
+        None => {
+            for drive in &drives {
+                drive.init().await?;
+            }
+            SyncEngine::run_pairs(remote.as_ref(), &drives, Duration::from_secs(30)).await;
         }
-        let local_files: Vec<FileWrapper> = drive.get_local_files().unwrap();
-        for file_wrapper in &local_files {
-            if existing_file_wrappers
-                .iter()
-                .any(|f| f.path.to_str().unwrap() == file_wrapper.path.to_str().unwrap())
-            {
-                debug!(
-                    "Not handling {} as a local file as it's already been handled",
-                    file_wrapper.path.display()
-                )
-            } else {
-                if file_wrapper.directory {
-                    debug!("Can't currently handle new directories");
-                    continue;
-                }
-                debug!(
-                    "Upload {} to Google Drive for the first time",
-                    file_wrapper.path.display()
+        Some("rebuild") => {
+            for drive in &drives {
+                let matched = drive.rebuild().await?;
+                println!(
+                    "Rebuilt the index of {}, matching {} local files",
+                    drive.root_dir().display(),
+                    matched
                 );
-                let upload_result = drive.upload_file(file_wrapper).await;
-                if let Err(result) = upload_result {
-                    error!(
-                        "Error occurred whilst uploading {} to Google Drive for the first time. {}",
-                        file_wrapper.path.display(),
-                        result
-                    )
-                }
             }
         }
-        thread::sleep(Duration::from_secs(30));
-    }
-}
-
-async fn handle_existing_file(drive: &Drive, file_wrapper: &FileWrapper) {
-    if file_wrapper.directory || file_wrapper.trashed {
-        return;
-    }
-    if !file_wrapper.path.exists() {
-        debug!(
-            "Creating file {} for the first time",
-            file_wrapper.path.display()
-        );
-        let created = drive.create_file(file_wrapper).await;
-        if created.is_err() {
-            error!("Unable to create file {}.", file_wrapper.path.display())
+        Some("reindex") => {
+            for drive in &drives {
+                let matched = drive.reindex().await?;
+                println!(
+                    "Reindexed the database of {}, matching {} local files",
+                    drive.root_dir().display(),
+                    matched
+                );
+            }
         }
-    } else {
-        let local_modified_time = file_wrapper
-            .path
-            .metadata()
-            .unwrap()
-            .modified()
-            .unwrap()
-            .duration_since(SystemTime::UNIX_EPOCH)
-            .unwrap()
-            .as_secs();
-        let remote_modified_time = file_wrapper
-            .last_accessed
-            .duration_since(SystemTime::UNIX_EPOCH)
-            .unwrap()
-            .as_secs();
-        if remote_modified_time == 0 {
-            debug!(
-                "Remote modified time wasn't updated properly for file {} when it was created",
-                file_wrapper.path.display()
-            );
-            let created = drive.create_file(file_wrapper).await;
-            if created.is_err() {
-                error!("Unable to create file {}.", file_wrapper.path.display())
+        Some("check") => {
+            let repair = match env::args().nth(2).as_deref() {
+                None => false,
+                Some("--repair") => true,
+                Some(option) => {
+                    return Err(RdriveError::Usage(format!("Unknown option {}", option)))
+                }
+            };
+            let mut issues = Vec::new();
+            for drive in &drives {
+                issues.extend(drive.check(repair).await?);
             }
-        } else if local_modified_time > remote_modified_time {
-            debug!(
-                "File {} has changed locally since last sync",
-                file_wrapper.path.display()
-            );
-            let uploaded = drive.upload_file(file_wrapper).await;
-            if uploaded.is_err() {
-                error!("Unable to create file {}.", file_wrapper.path.display())
+            for issue in &issues {
+                println!("{}", issue);
             }
-        } else if local_modified_time < remote_modified_time {
-            debug!(
-                "File {} has changed on remote since last sync",
-                file_wrapper.path.display()
-            );
-            let created = drive.create_file(file_wrapper).await;
-            if created.is_err() {
-                error!("Unable to create file {}.", file_wrapper.path.display())
+            println!("Found {} issues", issues.len());
+            if repair && !issues.is_empty() {
+                println!("Repaired the issues, see the log for any that couldn't be repaired");
             }
-        } else {
-            debug!("Nothing to do for file {}", file_wrapper.path.display());
         }
+        Some("history") => {
+            let path = get_path_argument(env::args().nth(2))?;
+            let drive = get_drive_for_path(&drives, &path)?;
+            for revision in drive.get_history(&path).await? {
+                println!(
+                    "{}  {}  {}  {}  {}{}",
+                    revision.id,
+                    revision.modified_time.to_rfc3339(),
+                    revision
+                        .size
+                        .map_or("-".to_string(), |size| size.to_string()),
+                    revision.author.as_deref().unwrap_or("-"),
+                    revision.md5_checksum.as_deref().unwrap_or("-"),
+                    if revision.keep_forever {
+                        "  (kept forever)"
+                    } else {
+                        ""
+                    }
+                );
+            }
+        }
+        Some("restore") => {
+            let args: Vec<String> = env::args().skip(2).collect();
+            match args.iter().position(|arg| arg == "--revision") {
+                Some(position) => {
+                    let path = get_path_argument(args.first().filter(|_| position > 0).cloned())?;
+                    let revision_id = args.get(position + 1).ok_or_else(|| {
+                        RdriveError::Usage("--revision needs the ID of a revision".to_string())
+                    })?;
+                    let upload = args.iter().any(|arg| arg == "--remote");
+                    let drive = get_drive_for_path(&drives, &path)?;
+                    drive.restore_revision(&path, revision_id, upload).await?;
+                    println!("Restored {} to revision {}", path.display(), revision_id);
+                }
+                None => {
+                    let mut entries = Vec::new();
+                    for drive in &drives {
+                        for entry in drive.get_trash()? {
+                            entries.push((drive, entry));
+                        }
+                    }
+                    match args.first() {
+                        None => {
+                            for (_, entry) in &entries {
+                                println!(
+                                    "{}  {}  {}",
+                                    entry.id,
+                                    entry.trashed_at,
+                                    entry.path.display()
+                                );
+                            }
+                        }
+                        Some(id) => {
+                            let (drive, entry) = entries
+                                .iter()
+                                .find(|(_, entry)| entry.id == *id)
+                                .ok_or_else(|| {
+                                    RdriveError::Usage(format!(
+                                        "Nothing in the trash has the ID {}",
+                                        id
+                                    ))
+                                })?;
+                            drive.restore(entry)?;
+                            println!("Restored {}", entry.path.display());
+                        }
+                    }
+                }
+            }
+        }
+        Some(command) => return Err(RdriveError::Usage(format!("Unknown command {}", command))),
     }
+    Ok(())
+}
+
+/// The sync pair whose root directory holds `path`.
+fn get_drive_for_path<'a>(drives: &'a [SyncEngine], path: &Path) -> Result<&'a SyncEngine> {
+    drives
+        .iter()
+        .find(|drive| path.starts_with(drive.root_dir()))
+        .ok_or_else(|| {
+            RdriveError::Path(
+                path.to_path_buf(),
+                "Path isn't in a synced directory".to_string(),
+            )
+        })
 }
 
-fn configure_logging() -> Result<Handle, SetLoggerError> {
+/// Paths are taken relative to the current directory, as synced files are stored by their
+/// absolute path.
+fn get_path_argument(argument: Option<String>) -> Result<PathBuf> {
+    let path = PathBuf::from(
+        argument
+            .ok_or_else(|| RdriveError::Usage("A path to a synced file is needed".to_string()))?,
+    );
+    if path.is_absolute() {
+        return Ok(path);
+    }
+    Ok(env::current_dir()?.join(path))
+}
+
+fn configure_logging() -> Result<Handle> {
     let stdout = ConsoleAppender::builder().build();
 
     let file = FileAppender::builder()
         .encoder(Box::new(PatternEncoder::new("{d} - {m}{n}")))
-        .build(get_base_log_path().join("rdrive.log"))
-        .unwrap();
+        .build(get_base_log_path()?.join("rdrive.log"))?;
 
     let config = Config::builder()
         .appender(
@@ -158,77 +200,26 @@ fn configure_logging() -> Result<Handle, SetLoggerError> {
                 .build("rdrive", LevelFilter::Debug),
         )
         .build(Root::builder().appender("stdout").build(LevelFilter::Warn))
-        .unwrap();
-
-    log4rs::init_config(config)
-}
-
-fn get_client() -> Client<HttpsConnector<HttpConnector>, Body> {
-    Client::builder(hyper_util::rt::TokioExecutor::new()).build(
-        hyper_rustls::HttpsConnectorBuilder::new()
-            .with_native_roots()
-            .expect("msg")
-            .https_or_http()
-            .enable_http2()
-            .build(),
-    )
-}
+        .map_err(|error| RdriveError::Config(error.to_string()))?;
 
-async fn get_authenticator() -> Authenticator<HttpsConnector<HttpConnector>> {
-    let secret: ApplicationSecret = yup_oauth2::read_application_secret("../secret.json")
-        .await
-        .expect("secret.json");
-    let token_file = &get_base_data_path()
-        .join("temp-key")
-        .to_str()
-        .unwrap()
-        .to_owned();
-    let connector = hyper_rustls::HttpsConnectorBuilder::new()
-        .with_native_roots()
-        .unwrap()
-        .https_only()
-        .enable_http2()
-        .build();
-    let executor = hyper_util::rt::TokioExecutor::new();
-    InstalledFlowAuthenticator::with_client(
-        secret,
-        InstalledFlowReturnMethod::Interactive,
-        CustomHyperClientBuilder::from(Client::builder(executor).build(connector)),
-    )
-    .persist_tokens_to_disk(token_file)
-    .build()
-    .await
-    .unwrap()
+    log4rs::init_config(config).map_err(|error| RdriveError::Config(error.to_string()))
 }
 
-fn get_db_connection() -> Connection {
-    let db_file = &get_base_data_path().join("rdrive.db");
-    fs::create_dir_all(db_file.parent().unwrap()).unwrap();
-    Connection::open(db_file).unwrap()
-}
-
-fn get_base_data_path() -> PathBuf {
-    let data_path = match env::consts::OS {
-        "windows" => PathBuf::from(env::var("LOCALAPPDATA").unwrap()),
-        "linux" => PathBuf::from(
-            env::var("XDG_DATA_HOME").unwrap_or(env::var("HOME").unwrap() + "/.local/share"),
-        ),
-        "macos" => PathBuf::from(env::var("HOME").unwrap() + "/Library"),
-        _ => PathBuf::new(),
-    };
-    data_path.join("rdrive")
-}
-
-fn get_base_log_path() -> PathBuf {
+fn get_base_log_path() -> Result<PathBuf> {
     let log_path = match env::consts::OS {
-        "windows" => PathBuf::from(env::var("LOCALAPPDATA").unwrap()),
+        "windows" => PathBuf::from(get_env_var("LOCALAPPDATA")?),
         "linux" => PathBuf::from(
-            env::var("XDG_DATA_HOME").unwrap_or(env::var("HOME").unwrap() + "/.local/share"),
+            env::var("XDG_DATA_HOME")
+                .or_else(|_| get_env_var("HOME").map(|home| home + "/.local/share"))?,
         ),
-        "macos" => PathBuf::from(env::var("HOME").unwrap())
+        "macos" => PathBuf::from(get_env_var("HOME")?)
             .join("Library")
             .join("Logs"),
         _ => PathBuf::new(),
     };
-    log_path.join("rdrive")
+    Ok(log_path.join("rdrive"))
+}
+
+fn get_env_var(name: &str) -> Result<String> {
+    env::var(name).map_err(|error| RdriveError::Config(format!("{} {}", name, error)))
 }
@@ -0,0 +1,200 @@
+use log::debug;
+use rusqlite::Connection;
+
+use crate::error::{RdriveError, Result};
+
+/// Ordered schema migrations. The database's `user_version` records how many have been applied,
+/// so new steps must only ever be appended.
+const MIGRATIONS: &[&str] = &[
+    "CREATE TABLE IF NOT EXISTS file (
+                id TEXT PRIMARY KEY,
+                name TEXT NOT NULL,
+                mime_type TEXT NOT NULL,
+                path TEXT NOT NULL,
+                directory INTEGER NOT NULL,
+                web_view_link TEXT,
+                owned_by_me INTEGER NOT NULL,
+                last_modified TEXT NOT NULL,
+                last_accessed TEXT NOT NULL,
+                trashed INTEGER NOT NULL
+            )",
+    "ALTER TABLE file ADD COLUMN version TEXT",
+    "CREATE TABLE file_parent (
+        file_id TEXT NOT NULL,
+        parent_id TEXT NOT NULL,
+        position INTEGER NOT NULL,
+        PRIMARY KEY (file_id, parent_id)
+    );
+    CREATE INDEX file_parent_parent_id ON file_parent (parent_id);",
+    "CREATE TABLE import (
+        file_id TEXT PRIMARY KEY,
+        path TEXT NOT NULL UNIQUE
+    )",
+    "ALTER TABLE file ADD COLUMN can_edit INTEGER NOT NULL DEFAULT 1",
+    "ALTER TABLE file ADD COLUMN shortcut_target_id TEXT",
+    "CREATE TABLE name_suffix (
+        file_id TEXT PRIMARY KEY,
+        suffix INTEGER NOT NULL
+    )",
+    "CREATE TABLE remote_name (
+        file_id TEXT PRIMARY KEY,
+        path TEXT NOT NULL,
+        name TEXT NOT NULL
+    );
+    CREATE INDEX remote_name_path ON remote_name (path);",
+    "ALTER TABLE file ADD COLUMN mode INTEGER;
+    ALTER TABLE file ADD COLUMN owner TEXT;
+    ALTER TABLE file ADD COLUMN group_name TEXT;",
+    "ALTER TABLE file ADD COLUMN symlink_target TEXT",
+];
+
+pub fn latest_version() -> i32 {
+    MIGRATIONS.len() as i32
+}
+
+pub fn migrate(conn: &Connection) -> Result<()> {
+    let mut current_version = get_user_version(conn)?;
+    if current_version == 0 {
+        current_version = detect_unversioned_schema(conn)?;
+    }
+    if current_version > latest_version() {
+        return Err(RdriveError::DbVersion {
+            found: current_version,
+            supported: latest_version(),
+        });
+    }
+    for (index, migration) in MIGRATIONS.iter().enumerate().skip(current_version as usize) {
+        let version = index as i32 + 1;
+        debug!("Migrating database to version {}", version);
+        let transaction = conn.unchecked_transaction()?;
+        transaction.execute_batch(migration)?;
+        transaction.pragma_update(None, "user_version", version)?;
+        transaction.commit()?;
+    }
+    Ok(())
+}
+
+fn get_user_version(conn: &Connection) -> Result<i32> {
+    Ok(conn.pragma_query_value(None, "user_version", |row| row.get(0))?)
+}
+
+/// Databases created before migrations were introduced have a `user_version` of 0, so work out
+/// which of the early migrations they already have from the shape of the `file` table.
+fn detect_unversioned_schema(conn: &Connection) -> Result<i32> {
+    let has_file_table: bool = conn.query_row(
+        "SELECT COUNT(*) > 0 FROM sqlite_master WHERE type = 'table' AND name = 'file'",
+        [],
+        |row| row.get(0),
+    )?;
+    if !has_file_table {
+        return Ok(0);
+    }
+    let has_version_column: bool = conn.query_row(
+        "SELECT COUNT(*) > 0 FROM pragma_table_info('file') WHERE name = 'version'",
+        [],
+        |row| row.get(0),
+    )?;
+    let version = if has_version_column { 2 } else { 1 };
+    conn.pragma_update(None, "user_version", version)?;
+    Ok(version)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const UNVERSIONED_FILE_TABLE: &str = "CREATE TABLE IF NOT EXISTS file (
+        id TEXT PRIMARY KEY,
+        name TEXT NOT NULL,
+        mime_type TEXT NOT NULL,
+        path TEXT NOT NULL,
+        directory INTEGER NOT NULL,
+        web_view_link TEXT,
+        owned_by_me INTEGER NOT NULL,
+        last_modified TEXT NOT NULL,
+        last_accessed TEXT NOT NULL,
+        trashed INTEGER NOT NULL
+    )";
+
+    #[test]
+    fn migrate_should_create_latest_schema_for_new_database() {
+        let connection = Connection::open_in_memory().unwrap();
+        let result = migrate(&connection);
+        assert!(result.is_ok());
+
+        assert_eq!(get_user_version(&connection).unwrap(), latest_version());
+        assert!(has_column(&connection, "file", "version"));
+    }
+
+    #[test]
+    fn migrate_should_upgrade_unversioned_database_and_keep_files() {
+        let connection = Connection::open_in_memory().unwrap();
+        connection.execute(UNVERSIONED_FILE_TABLE, []).unwrap();
+        connection
+            .execute(
+                "INSERT INTO file VALUES ('id', 'name', 'text/plain', 'path', 0, NULL, 1, '2024-01-01T00:00:00+00:00', '2024-01-01T00:00:00+00:00', 0)",
+                [],
+            )
+            .unwrap();
+        let result = migrate(&connection);
+        assert!(result.is_ok());
+
+        assert_eq!(get_user_version(&connection).unwrap(), latest_version());
+        assert!(has_column(&connection, "file", "version"));
+        let name: String = connection
+            .query_row("SELECT name FROM file WHERE id = 'id'", [], |row| {
+                row.get(0)
+            })
+            .unwrap();
+        assert_eq!(name, "name");
+    }
+
+    #[test]
+    fn migrate_should_recognise_unversioned_database_with_version_column() {
+        let connection = Connection::open_in_memory().unwrap();
+        connection.execute(UNVERSIONED_FILE_TABLE, []).unwrap();
+        connection
+            .execute("ALTER TABLE file ADD COLUMN version TEXT", [])
+            .unwrap();
+        let result = migrate(&connection);
+        assert!(result.is_ok());
+
+        assert_eq!(get_user_version(&connection).unwrap(), latest_version());
+    }
+
+    #[test]
+    fn migrate_should_be_idempotent() {
+        let connection = Connection::open_in_memory().unwrap();
+        assert!(migrate(&connection).is_ok());
+        assert!(migrate(&connection).is_ok());
+
+        assert_eq!(get_user_version(&connection).unwrap(), latest_version());
+    }
+
+    #[test]
+    fn migrate_should_error_if_database_is_newer_than_binary() {
+        let connection = Connection::open_in_memory().unwrap();
+        connection
+            .pragma_update(None, "user_version", latest_version() + 1)
+            .unwrap();
+        let result = migrate(&connection);
+
+        match result {
+            Err(RdriveError::DbVersion { found, supported }) => {
+                assert_eq!(found, latest_version() + 1);
+                assert_eq!(supported, latest_version());
+            }
+            _ => panic!("Expected a DbVersion error"),
+        }
+    }
+
+    fn has_column(connection: &Connection, table: &str, column: &str) -> bool {
+        connection
+            .query_row(
+                "SELECT COUNT(*) > 0 FROM pragma_table_info(?1) WHERE name = ?2",
+                [table, column],
+                |row| row.get(0),
+            )
+            .unwrap()
+    }
+}
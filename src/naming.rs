@@ -0,0 +1,211 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+/// The longest file name, in bytes, most file systems allow.
+pub const MAX_NAME_BYTES: usize = 255;
+const REPLACEMENT: char = '_';
+const WINDOWS_INVALID_CHARS: &[char] = &['<', '>', ':', '"', '/', '\\', '|', '?', '*'];
+const WINDOWS_RESERVED_NAMES: &[&str] = &[
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8",
+    "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+/// The rules Drive names are made to follow before they are used as local file names.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NameProfile {
+    /// The rules of the platform rdrive is running on.
+    #[default]
+    Native,
+    /// Only `/` is replaced, which suits Linux and macOS file systems.
+    Unix,
+    /// The rules of Windows, which also suit exFAT and FAT32 drives on any platform.
+    Windows,
+}
+
+impl NameProfile {
+    fn resolve(self) -> NameProfile {
+        match self {
+            NameProfile::Native if cfg!(windows) => NameProfile::Windows,
+            NameProfile::Native => NameProfile::Unix,
+            profile => profile,
+        }
+    }
+}
+
+/// Replaces the parts of a Drive name that aren't allowed in a local file name under `profile`.
+pub fn sanitise(name: &str, profile: NameProfile) -> String {
+    let name = match profile.resolve() {
+        NameProfile::Windows => sanitise_for_windows(name),
+        _ => name.replace(['/', '\0'], "_"),
+    };
+    match name.as_str() {
+        "" | "." => REPLACEMENT.to_string(),
+        ".." => REPLACEMENT.to_string().repeat(2),
+        _ => name,
+    }
+}
+
+fn sanitise_for_windows(name: &str) -> String {
+    let name: String = name
+        .chars()
+        .map(|c| {
+            if c.is_control() || WINDOWS_INVALID_CHARS.contains(&c) {
+                REPLACEMENT
+            } else {
+                c
+            }
+        })
+        .collect();
+    let name = name.trim_end_matches(['.', ' ']);
+    let stem_length = name.find('.').unwrap_or(name.len());
+    let is_reserved = WINDOWS_RESERVED_NAMES
+        .iter()
+        .any(|reserved| reserved.eq_ignore_ascii_case(name[..stem_length].trim_end()));
+    if is_reserved {
+        format!(
+            "{}{}{}",
+            &name[..stem_length],
+            REPLACEMENT,
+            &name[stem_length..]
+        )
+    } else {
+        name.to_string()
+    }
+}
+
+/// Turns a Drive name into the local name of a file, sanitised under `profile` with the
+/// duplicate `suffix` added. The name is shortened so it still fits in [`MAX_NAME_BYTES`] once
+/// `reserved_bytes` more have been added to the end, keeping the extension when
+/// `split_extension` is set.
+pub fn get_local_name(
+    name: &str,
+    profile: NameProfile,
+    suffix: usize,
+    split_extension: bool,
+    reserved_bytes: usize,
+) -> String {
+    let name = sanitise(name, profile);
+    let suffix_bytes = add_duplicate_suffix("", suffix, false).len();
+    let max_bytes = MAX_NAME_BYTES.saturating_sub(reserved_bytes + suffix_bytes);
+    let name = truncate(&name, max_bytes, split_extension);
+    add_duplicate_suffix(&name, suffix, split_extension)
+}
+
+fn truncate(name: &str, max_bytes: usize, split_extension: bool) -> String {
+    if name.len() <= max_bytes {
+        return name.to_string();
+    }
+    let (stem, extension) = match name.rfind('.') {
+        Some(index) if split_extension && index > 0 && name.len() - index < max_bytes => {
+            name.split_at(index)
+        }
+        _ => (name, ""),
+    };
+    let mut stem_length = max_bytes - extension.len();
+    while !stem.is_char_boundary(stem_length) {
+        stem_length -= 1;
+    }
+    let stem = stem[..stem_length].trim_end_matches(['.', ' ']);
+    if stem.is_empty() {
+        format!("{}{}", REPLACEMENT, extension)
+    } else {
+        format!("{}{}", stem, extension)
+    }
+}
+
+/// Adds ` (n)` to a file name to tell apart files with the same name in one folder, such as
+/// `report (1).pdf`. The suffix goes before the extension when `split_extension` is set and the
+/// name has one.
+pub fn add_duplicate_suffix(name: &str, suffix: usize, split_extension: bool) -> String {
+    if suffix == 0 {
+        return name.to_string();
+    }
+    match name.rfind('.') {
+        Some(index) if split_extension && index > 0 => {
+            format!("{} ({}){}", &name[..index], suffix, &name[index..])
+        }
+        _ => format!("{} ({})", name, suffix),
+    }
+}
+
+/// Whether `dir` is on a file system that treats names that only differ by case as the same
+/// file, found out by creating a probe file and looking for it with its name in upper case.
+pub fn is_case_insensitive(dir: &Path) -> io::Result<bool> {
+    fs::create_dir_all(dir)?;
+    let probe_path = dir.join(".rdrive-case-probe");
+    fs::write(&probe_path, "")?;
+    let result = dir.join(".RDRIVE-CASE-PROBE").try_exists();
+    fs::remove_file(&probe_path)?;
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use super::*;
+
+    #[test]
+    fn add_duplicate_suffix_should_add_suffix_before_extension() {
+        assert_eq!(add_duplicate_suffix("report.pdf", 0, true), "report.pdf");
+        assert_eq!(
+            add_duplicate_suffix("report.pdf", 1, true),
+            "report (1).pdf"
+        );
+        assert_eq!(
+            add_duplicate_suffix("report.v2.pdf", 2, true),
+            "report.v2 (2).pdf"
+        );
+    }
+
+    #[test]
+    fn sanitise_should_only_replace_slashes_for_unix() {
+        assert_eq!(sanitise("a/b:c?.txt", NameProfile::Unix), "a_b:c?.txt");
+        assert_eq!(sanitise("..", NameProfile::Unix), "__");
+        assert_eq!(sanitise("", NameProfile::Unix), "_");
+    }
+
+    #[test]
+    fn sanitise_should_replace_invalid_windows_names() {
+        assert_eq!(
+            sanitise("a/b:c?\"d\".txt", NameProfile::Windows),
+            "a_b_c__d_.txt"
+        );
+        assert_eq!(sanitise("notes. . ", NameProfile::Windows), "notes");
+        assert_eq!(sanitise("con.txt", NameProfile::Windows), "con_.txt");
+        assert_eq!(sanitise("LPT1", NameProfile::Windows), "LPT1_");
+        assert_eq!(sanitise("console.txt", NameProfile::Windows), "console.txt");
+        assert_eq!(sanitise("...", NameProfile::Windows), "_");
+    }
+
+    #[test]
+    fn get_local_name_should_keep_extension_and_suffix_when_shortening() {
+        let name = format!("{}.pdf", "é".repeat(200));
+        let local_name = get_local_name(&name, NameProfile::Unix, 1, true, 0);
+        assert_eq!(local_name.len(), 254);
+        assert!(local_name.ends_with("é (1).pdf"));
+        let local_name = get_local_name(&name, NameProfile::Unix, 0, false, 5);
+        assert_eq!(local_name.len(), 250);
+        assert!(local_name.ends_with('é'));
+    }
+
+    #[test]
+    fn add_duplicate_suffix_should_add_suffix_to_end_without_extension() {
+        assert_eq!(add_duplicate_suffix("report", 1, true), "report (1)");
+        assert_eq!(add_duplicate_suffix(".bashrc", 1, true), ".bashrc (1)");
+        assert_eq!(add_duplicate_suffix("v1.2", 1, false), "v1.2 (1)");
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn is_case_insensitive_should_detect_case_sensitive_directory() {
+        let dir = env::temp_dir().join("rdrive-case-test");
+        assert!(!is_case_insensitive(&dir).unwrap());
+        assert!(!dir.join(".rdrive-case-probe").exists());
+        fs::remove_dir(dir).unwrap();
+    }
+}
@@ -0,0 +1,249 @@
+use std::collections::HashMap;
+use std::io;
+use std::path::Path;
+
+const MODE_PROPERTY: &str = "rdriveMode";
+const OWNER_PROPERTY: &str = "rdriveOwner";
+const GROUP_PROPERTY: &str = "rdriveGroup";
+/// Only the read, write and execute bits are kept, so setuid, setgid and sticky bits set by
+/// anyone who can edit a file are never applied locally.
+const PERMISSION_BITS: u32 = 0o777;
+
+/// The POSIX permissions of a file, stored in the Drive `appProperties` of the file.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Permissions {
+    pub mode: Option<u32>,
+    pub owner: Option<String>,
+    pub group: Option<String>,
+}
+
+impl Permissions {
+    pub fn is_empty(&self) -> bool {
+        self.mode.is_none() && self.owner.is_none() && self.group.is_none()
+    }
+
+    pub fn to_properties(&self) -> HashMap<String, String> {
+        let mut properties = HashMap::new();
+        if let Some(mode) = self.mode {
+            properties.insert(MODE_PROPERTY.to_string(), format!("{:o}", mode));
+        }
+        if let Some(owner) = &self.owner {
+            properties.insert(OWNER_PROPERTY.to_string(), owner.clone());
+        }
+        if let Some(group) = &self.group {
+            properties.insert(GROUP_PROPERTY.to_string(), group.clone());
+        }
+        properties
+    }
+
+    pub fn from_properties(properties: &HashMap<String, String>) -> Permissions {
+        Permissions {
+            mode: properties
+                .get(MODE_PROPERTY)
+                .and_then(|mode| u32::from_str_radix(mode, 8).ok())
+                .filter(|mode| *mode <= 0o7777)
+                .map(|mode| mode & PERMISSION_BITS),
+            owner: properties.get(OWNER_PROPERTY).cloned(),
+            group: properties.get(GROUP_PROPERTY).cloned(),
+        }
+    }
+}
+
+/// Reads the permissions of a local file, including the names of its owner and group when
+/// `include_owner` is set.
+#[cfg(unix)]
+pub fn read(path: &Path, include_owner: bool) -> io::Result<Permissions> {
+    use std::os::unix::fs::MetadataExt;
+
+    let metadata = std::fs::metadata(path)?;
+    let (owner, group) = if include_owner {
+        (
+            find_user_name(metadata.uid()),
+            find_group_name(metadata.gid()),
+        )
+    } else {
+        (None, None)
+    };
+    Ok(Permissions {
+        mode: Some(metadata.mode() & PERMISSION_BITS),
+        owner,
+        group,
+    })
+}
+
+#[cfg(not(unix))]
+pub fn read(_path: &Path, _include_owner: bool) -> io::Result<Permissions> {
+    Ok(Permissions::default())
+}
+
+/// Applies stored permissions to a downloaded file. Files without a stored mode are given the
+/// default mode for new files under `umask`. The owner and group are only changed when
+/// `apply_owner` is set and rdrive is allowed to change them.
+#[cfg(unix)]
+pub fn apply(
+    path: &Path,
+    permissions: &Permissions,
+    umask: u32,
+    apply_owner: bool,
+) -> io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let mode = permissions.mode.unwrap_or(0o666 & !umask) & PERMISSION_BITS;
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode))?;
+    if !apply_owner {
+        return Ok(());
+    }
+    let uid = permissions
+        .owner
+        .as_ref()
+        .and_then(|owner| find_user_id(owner));
+    let gid = permissions
+        .group
+        .as_ref()
+        .and_then(|group| find_group_id(group));
+    if uid.is_some() || gid.is_some() {
+        if let Err(error) = std::os::unix::fs::chown(path, uid, gid) {
+            log::debug!(
+                "Unable to change the owner of {}. {}",
+                path.display(),
+                error
+            );
+        }
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub fn apply(
+    _path: &Path,
+    _permissions: &Permissions,
+    _umask: u32,
+    _apply_owner: bool,
+) -> io::Result<()> {
+    Ok(())
+}
+
+#[cfg(unix)]
+fn find_user_name(uid: u32) -> Option<String> {
+    lookup(
+        |entry, buffer, length, result| unsafe {
+            libc::getpwuid_r(uid, entry, buffer, length, result)
+        },
+        |passwd: &libc::passwd| to_string(passwd.pw_name),
+    )?
+}
+
+#[cfg(unix)]
+fn find_group_name(gid: u32) -> Option<String> {
+    lookup(
+        |entry, buffer, length, result| unsafe {
+            libc::getgrgid_r(gid, entry, buffer, length, result)
+        },
+        |group: &libc::group| to_string(group.gr_name),
+    )?
+}
+
+#[cfg(unix)]
+fn find_user_id(name: &str) -> Option<u32> {
+    let name = std::ffi::CString::new(name).ok()?;
+    lookup(
+        |entry, buffer, length, result| unsafe {
+            libc::getpwnam_r(name.as_ptr(), entry, buffer, length, result)
+        },
+        |passwd: &libc::passwd| passwd.pw_uid,
+    )
+}
+
+#[cfg(unix)]
+fn find_group_id(name: &str) -> Option<u32> {
+    let name = std::ffi::CString::new(name).ok()?;
+    lookup(
+        |entry, buffer, length, result| unsafe {
+            libc::getgrnam_r(name.as_ptr(), entry, buffer, length, result)
+        },
+        |group: &libc::group| group.gr_gid,
+    )
+}
+
+/// Looks up a user or group through the system's name service, so users from LDAP or sssd are
+/// found as well as those in `/etc/passwd`. `call` is one of the reentrant `get*_r` functions,
+/// which is retried with a bigger buffer while the entry doesn't fit.
+#[cfg(unix)]
+fn lookup<T, R>(
+    mut call: impl FnMut(*mut T, *mut libc::c_char, libc::size_t, *mut *mut T) -> libc::c_int,
+    extract: impl FnOnce(&T) -> R,
+) -> Option<R> {
+    const MAX_BUFFER_SIZE: usize = 1024 * 1024;
+
+    let mut entry = std::mem::MaybeUninit::<T>::uninit();
+    let mut buffer: Vec<libc::c_char> = vec![0; 1024];
+    loop {
+        let mut result = std::ptr::null_mut();
+        let code = call(
+            entry.as_mut_ptr(),
+            buffer.as_mut_ptr(),
+            buffer.len(),
+            &mut result,
+        );
+        if code == libc::ERANGE && buffer.len() < MAX_BUFFER_SIZE {
+            buffer.resize(buffer.len() * 2, 0);
+            continue;
+        }
+        if code != 0 || result.is_null() {
+            return None;
+        }
+        // The entry was filled in by the call, and the strings it points to are in `buffer`,
+        // which outlives `extract`.
+        return Some(extract(unsafe { &*result }));
+    }
+}
+
+#[cfg(unix)]
+fn to_string(name: *const libc::c_char) -> Option<String> {
+    if name.is_null() {
+        return None;
+    }
+    let name = unsafe { std::ffi::CStr::from_ptr(name) };
+    name.to_str().ok().map(str::to_string)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn from_properties_should_read_properties_written_by_to_properties() {
+        let permissions = Permissions {
+            mode: Some(0o755),
+            owner: Some("alice".to_string()),
+            group: None,
+        };
+        let properties = permissions.to_properties();
+        assert_eq!(properties.get(MODE_PROPERTY), Some(&"755".to_string()));
+        assert_eq!(Permissions::from_properties(&properties), permissions);
+    }
+
+    #[test]
+    fn from_properties_should_ignore_invalid_mode() {
+        let properties = HashMap::from([(MODE_PROPERTY.to_string(), "abc".to_string())]);
+        assert!(Permissions::from_properties(&properties).is_empty());
+        let properties = HashMap::from([(MODE_PROPERTY.to_string(), "17777".to_string())]);
+        assert!(Permissions::from_properties(&properties).is_empty());
+    }
+
+    #[test]
+    fn from_properties_should_drop_setuid_setgid_and_sticky_bits() {
+        let properties = HashMap::from([(MODE_PROPERTY.to_string(), "7755".to_string())]);
+        assert_eq!(Permissions::from_properties(&properties).mode, Some(0o755));
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn find_user_and_group_should_look_up_root() {
+        assert_eq!(find_user_name(0).as_deref(), Some("root"));
+        assert_eq!(find_user_id("root"), Some(0));
+        assert_eq!(find_group_id("rdrive-missing-group"), None);
+        let group = find_group_name(0).unwrap();
+        assert_eq!(find_group_id(&group), Some(0));
+    }
+}
@@ -0,0 +1,161 @@
+use async_trait::async_trait;
+use chrono::{DateTime, FixedOffset};
+use drive3::common::Body;
+use drive3::hyper_rustls::HttpsConnector;
+use drive3::hyper_util::client::legacy::connect::HttpConnector;
+use drive3::hyper_util::client::legacy::Client;
+use drive3::{hyper_rustls, hyper_util, DriveHub};
+
+use crate::config::RemoteConfig;
+use crate::drive::FileWrapper;
+use crate::error::{RdriveError, Result};
+use crate::permissions::Permissions;
+use crate::remote::google::GoogleDrive;
+use crate::remote::webdav::WebDav;
+
+#[cfg(test)]
+pub mod fake;
+pub mod google;
+pub mod webdav;
+
+/// A file or directory as reported by a remote, before it has been mapped to a local path.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct RemoteFile {
+    pub id: String,
+    pub name: String,
+    pub mime_type: String,
+    pub parents: Vec<String>,
+    pub web_view_link: Option<String>,
+    pub owned_by_me: bool,
+    pub modified_time: DateTime<FixedOffset>,
+    pub trashed: bool,
+    pub version: Option<String>,
+    pub can_edit: bool,
+    /// The ID of the file a shortcut points to.
+    pub shortcut_target_id: Option<String>,
+    /// The POSIX permissions stored with the file, if any.
+    pub permissions: Permissions,
+    /// For symlinks stored by rdrive, the path the symlink points to.
+    pub symlink_target: Option<String>,
+    /// The size of the content in bytes, if the remote reports it.
+    pub size: Option<u64>,
+    /// The MD5 checksum of the content as lowercase hex, if the remote reports it.
+    pub md5_checksum: Option<String>,
+}
+
+#[cfg(test)]
+impl RemoteFile {
+    /// An editable text file at the top of the remote, for tests to override the fields they
+    /// care about.
+    pub(crate) fn test_default() -> RemoteFile {
+        RemoteFile {
+            id: "id".to_string(),
+            name: "name".to_string(),
+            mime_type: "text/plain".to_string(),
+            parents: Vec::new(),
+            web_view_link: None,
+            owned_by_me: true,
+            modified_time: DateTime::parse_from_rfc3339("2024-01-01T00:00:00+00:00").unwrap(),
+            trashed: false,
+            version: Some("1".to_string()),
+            can_edit: true,
+            shortcut_target_id: None,
+            permissions: Permissions::default(),
+            symlink_target: None,
+            size: None,
+            md5_checksum: None,
+        }
+    }
+}
+
+/// A stored version of a file's content.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Revision {
+    pub id: String,
+    pub modified_time: DateTime<FixedOffset>,
+    pub size: Option<u64>,
+    /// The name of the user who uploaded the revision.
+    pub author: Option<String>,
+    pub md5_checksum: Option<String>,
+    /// Whether the revision is kept even once it is no longer one of the most recent.
+    pub keep_forever: bool,
+}
+
+#[async_trait(?Send)]
+pub trait Remote {
+    async fn list_files(&self) -> Result<Vec<RemoteFile>>;
+
+    async fn download(&self, file_wrapper: &FileWrapper) -> Result<Vec<u8>>;
+
+    /// Converts a Google-native file to `mime_type` and returns the converted content.
+    async fn export(&self, file_wrapper: &FileWrapper, mime_type: &str) -> Result<Vec<u8>>;
+
+    async fn upload(
+        &self,
+        file_wrapper: &FileWrapper,
+        parent_id: Option<&str>,
+    ) -> Result<RemoteFile>;
+
+    /// Uploads a new local file, converting it to the Google-native `mime_type`.
+    async fn import(
+        &self,
+        file_wrapper: &FileWrapper,
+        parent_id: Option<&str>,
+        mime_type: &str,
+    ) -> Result<RemoteFile>;
+
+    async fn update(&self, file_wrapper: &FileWrapper) -> Result<RemoteFile>;
+
+    async fn move_file(
+        &self,
+        file_wrapper: &FileWrapper,
+        parent_id: Option<&str>,
+        name: &str,
+    ) -> Result<RemoteFile>;
+
+    /// Moves the file to the remote's trash, so a file deleted by mistake can still be restored
+    /// there rather than being lost.
+    async fn trash(&self, file_wrapper: &FileWrapper) -> Result<()>;
+
+    /// The stored revisions of a file, oldest first.
+    async fn list_revisions(&self, file_wrapper: &FileWrapper) -> Result<Vec<Revision>>;
+
+    async fn download_revision(
+        &self,
+        file_wrapper: &FileWrapper,
+        revision_id: &str,
+    ) -> Result<Vec<u8>>;
+
+    /// Keeps the current revision of the file `id` forever, rather than letting the remote
+    /// delete it once newer revisions have been uploaded.
+    async fn keep_forever(&self, id: &str) -> Result<()>;
+}
+
+pub async fn create_remote(remote_config: &RemoteConfig) -> Result<Box<dyn Remote>> {
+    Ok(match remote_config {
+        RemoteConfig::Google { shared_drives } => Box::new(GoogleDrive::new(
+            DriveHub::new(get_client()?, google::get_authenticator().await?),
+            shared_drives.clone(),
+        )),
+        RemoteConfig::WebDav {
+            url,
+            username,
+            password,
+        } => Box::new(WebDav::new(get_client()?, url, username, password)?),
+    })
+}
+
+pub fn get_client() -> Result<Client<HttpsConnector<HttpConnector>, Body>> {
+    let connector = hyper_rustls::HttpsConnectorBuilder::new()
+        .with_native_roots()
+        .map_err(|error| {
+            RdriveError::Config(format!(
+                "Failed to load the native root certificates {}",
+                error
+            ))
+        })?
+        .https_or_http()
+        .enable_http2()
+        .build();
+    Ok(Client::builder(hyper_util::rt::TokioExecutor::new()).build(connector))
+}
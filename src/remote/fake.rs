@@ -0,0 +1,219 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fs;
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+
+use crate::drive::FileWrapper;
+use crate::error::{RdriveError, Result};
+use crate::remote::{Remote, RemoteFile, Revision};
+
+/// A remote held in memory, so sync cycles can be tested without a server. Every call that
+/// reads content or changes the remote is recorded, in order, as `"<call> <id or name>"`.
+#[derive(Default)]
+pub struct FakeRemote {
+    files: RefCell<Vec<RemoteFile>>,
+    contents: RefCell<HashMap<String, Vec<u8>>>,
+    calls: RefCell<Vec<String>>,
+}
+
+impl FakeRemote {
+    pub fn new(files: Vec<(RemoteFile, &[u8])>) -> FakeRemote {
+        let remote = FakeRemote::default();
+        for (file, content) in files {
+            remote.put(file, content);
+        }
+        remote
+    }
+
+    /// Adds or replaces a file, as if it had been changed on the remote by someone else.
+    pub fn put(&self, file: RemoteFile, content: &[u8]) {
+        self.contents
+            .borrow_mut()
+            .insert(file.id.clone(), content.to_vec());
+        let mut files = self.files.borrow_mut();
+        files.retain(|stored| stored.id != file.id);
+        files.push(file);
+    }
+
+    pub fn get(&self, id: &str) -> Option<RemoteFile> {
+        self.files
+            .borrow()
+            .iter()
+            .find(|file| file.id == id)
+            .cloned()
+    }
+
+    pub fn content(&self, id: &str) -> Option<Vec<u8>> {
+        self.contents.borrow().get(id).cloned()
+    }
+
+    /// Drops the content of a file, so downloading it fails.
+    pub fn remove_content(&self, id: &str) {
+        self.contents.borrow_mut().remove(id);
+    }
+
+    pub fn calls(&self) -> Vec<String> {
+        self.calls.borrow().clone()
+    }
+
+    /// Whether any recorded call starts with `call`, such as `"download"` or `"trash id"`.
+    pub fn was_called(&self, call: &str) -> bool {
+        self.calls
+            .borrow()
+            .iter()
+            .any(|made| made.starts_with(call))
+    }
+
+    fn record(&self, call: &str, subject: &str) {
+        self.calls
+            .borrow_mut()
+            .push(format!("{} {}", call, subject));
+    }
+
+    fn read_content(&self, id: &str) -> Result<Vec<u8>> {
+        self.content(id)
+            .ok_or_else(|| RdriveError::Api(format!("File {} not found", id)))
+    }
+
+    fn create(
+        &self,
+        file_wrapper: &FileWrapper,
+        parent_id: Option<&str>,
+        mime_type: &str,
+    ) -> Result<RemoteFile> {
+        let file = RemoteFile {
+            id: format!("uploaded-{}", self.files.borrow().len()),
+            name: file_wrapper.name.clone(),
+            mime_type: mime_type.to_string(),
+            parents: parent_id.map(str::to_string).into_iter().collect(),
+            ..RemoteFile::test_default()
+        };
+        self.store(file, file_wrapper)
+    }
+
+    /// Stores the local content as a new version of the file, keeping the local modified time as
+    /// the real remotes do.
+    fn store(&self, file: RemoteFile, file_wrapper: &FileWrapper) -> Result<RemoteFile> {
+        let (content, metadata) = if file_wrapper.symlink_target.is_some() {
+            (Vec::new(), fs::symlink_metadata(&file_wrapper.path)?)
+        } else {
+            (
+                fs::read(&file_wrapper.path)?,
+                fs::metadata(&file_wrapper.path)?,
+            )
+        };
+        let version = file
+            .version
+            .as_ref()
+            .and_then(|version| version.parse::<u64>().ok())
+            .map_or(1, |version| version + 1);
+        let file = RemoteFile {
+            version: Some(version.to_string()),
+            modified_time: DateTime::<Utc>::from(metadata.modified()?).into(),
+            permissions: file_wrapper.permissions.clone(),
+            symlink_target: file_wrapper.symlink_target.clone(),
+            ..file
+        };
+        self.put(file.clone(), &content);
+        Ok(file)
+    }
+}
+
+#[async_trait(?Send)]
+impl Remote for FakeRemote {
+    async fn list_files(&self) -> Result<Vec<RemoteFile>> {
+        Ok(self.files.borrow().clone())
+    }
+
+    async fn download(&self, file_wrapper: &FileWrapper) -> Result<Vec<u8>> {
+        self.record("download", &file_wrapper.id);
+        self.read_content(&file_wrapper.id)
+    }
+
+    async fn export(&self, file_wrapper: &FileWrapper, _mime_type: &str) -> Result<Vec<u8>> {
+        self.record("export", &file_wrapper.id);
+        self.read_content(&file_wrapper.id)
+    }
+
+    async fn upload(
+        &self,
+        file_wrapper: &FileWrapper,
+        parent_id: Option<&str>,
+    ) -> Result<RemoteFile> {
+        self.record("upload", &file_wrapper.name);
+        self.create(file_wrapper, parent_id, &file_wrapper.mime_type)
+    }
+
+    async fn import(
+        &self,
+        file_wrapper: &FileWrapper,
+        parent_id: Option<&str>,
+        mime_type: &str,
+    ) -> Result<RemoteFile> {
+        self.record("import", &file_wrapper.name);
+        self.create(file_wrapper, parent_id, mime_type)
+    }
+
+    async fn update(&self, file_wrapper: &FileWrapper) -> Result<RemoteFile> {
+        self.record("update", &file_wrapper.id);
+        let file = self
+            .get(&file_wrapper.id)
+            .ok_or_else(|| RdriveError::Api(format!("File {} not found", file_wrapper.id)))?;
+        self.store(file, file_wrapper)
+    }
+
+    async fn move_file(
+        &self,
+        file_wrapper: &FileWrapper,
+        parent_id: Option<&str>,
+        name: &str,
+    ) -> Result<RemoteFile> {
+        self.record("move", &file_wrapper.id);
+        let file = self
+            .get(&file_wrapper.id)
+            .ok_or_else(|| RdriveError::Api(format!("File {} not found", file_wrapper.id)))?;
+        let file = RemoteFile {
+            name: name.to_string(),
+            parents: parent_id.map(str::to_string).into_iter().collect(),
+            ..file
+        };
+        self.put(file.clone(), &self.read_content(&file.id)?);
+        Ok(file)
+    }
+
+    async fn trash(&self, file_wrapper: &FileWrapper) -> Result<()> {
+        self.record("trash", &file_wrapper.id);
+        let file = self
+            .get(&file_wrapper.id)
+            .ok_or_else(|| RdriveError::Api(format!("File {} not found", file_wrapper.id)))?;
+        let content = self.read_content(&file.id)?;
+        self.put(
+            RemoteFile {
+                trashed: true,
+                ..file
+            },
+            &content,
+        );
+        Ok(())
+    }
+
+    async fn list_revisions(&self, _file_wrapper: &FileWrapper) -> Result<Vec<Revision>> {
+        Ok(Vec::new())
+    }
+
+    async fn download_revision(
+        &self,
+        file_wrapper: &FileWrapper,
+        revision_id: &str,
+    ) -> Result<Vec<u8>> {
+        self.record("download_revision", revision_id);
+        self.read_content(&file_wrapper.id)
+    }
+
+    async fn keep_forever(&self, id: &str) -> Result<()> {
+        self.record("keep_forever", id);
+        Ok(())
+    }
+}
@@ -0,0 +1,632 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fs;
+use std::io::Cursor;
+use std::path::Path;
+
+use async_recursion::async_recursion;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use drive3::api::{File, Scope};
+use drive3::common::{to_bytes, ReadSeek};
+use drive3::hyper_rustls::HttpsConnector;
+use drive3::hyper_util::client::legacy::connect::HttpConnector;
+use drive3::hyper_util::client::legacy::Client;
+use drive3::yup_oauth2::authenticator::Authenticator;
+use drive3::yup_oauth2::{
+    ApplicationSecret, CustomHyperClientBuilder, InstalledFlowAuthenticator,
+    InstalledFlowReturnMethod,
+};
+use drive3::{hyper_rustls, hyper_util, yup_oauth2, DriveHub};
+use log::{debug, error};
+
+use crate::config::{get_base_data_path, SharedDriveConfig};
+use crate::drive::{FileWrapper, DIRECTORY_MIME_TYPE};
+use crate::error::{RdriveError, Result};
+use crate::permissions::Permissions;
+use crate::remote::{Remote, RemoteFile, Revision};
+
+/// Symlinks are stored as small files with the path they point to kept in this property.
+const SYMLINK_TARGET_PROPERTY: &str = "rdriveSymlinkTarget";
+const FILE_FIELDS: &str = "id, kind, name, description, kind, mimeType, parents, ownedByMe, webContentLink, webViewLink, modifiedTime, trashed, version, driveId, capabilities/canEdit, shortcutDetails/targetId, appProperties, size, md5Checksum";
+const REVISION_LIST_FIELDS: &str = "nextPageToken, revisions(id, modifiedTime, size, lastModifyingUser/displayName, md5Checksum, keepForever)";
+
+pub struct GoogleDrive {
+    hub: DriveHub<HttpsConnector<HttpConnector>>,
+    shared_drives: Vec<SharedDriveConfig>,
+    corpora: RefCell<HashMap<Option<String>, Corpus>>,
+}
+
+/// The last listing of My Drive or a shared drive, with the change token it was taken at.
+#[derive(Clone)]
+struct Corpus {
+    page_token: String,
+    files: Vec<RemoteFile>,
+}
+
+impl GoogleDrive {
+    pub fn new(
+        hub: DriveHub<HttpsConnector<HttpConnector>>,
+        shared_drives: Vec<SharedDriveConfig>,
+    ) -> GoogleDrive {
+        GoogleDrive {
+            hub,
+            shared_drives,
+            corpora: RefCell::new(HashMap::new()),
+        }
+    }
+
+    /// Lists the files in My Drive, or in a shared drive if `drive_id` is set.
+    #[async_recursion(?Send)]
+    async fn fetch_files(
+        &self,
+        drive_id: Option<&'async_recursion str>,
+        page_token: Option<String>,
+    ) -> Result<Vec<File>> {
+        let fields = format!("nextPageToken, files({})", FILE_FIELDS);
+        let mut file_list_call = self
+            .hub
+            .files()
+            .list()
+            .add_scope(Scope::Full)
+            .supports_all_drives(true)
+            .param("fields", fields.as_str());
+        file_list_call = match drive_id {
+            Some(drive_id) => file_list_call
+                .corpora("drive")
+                .drive_id(drive_id)
+                .include_items_from_all_drives(true),
+            None => file_list_call.corpora("user"),
+        };
+        if let Some(token) = page_token {
+            file_list_call = file_list_call.page_token(token.as_str())
+        }
+        let response = file_list_call.doit().await?;
+        let mut files = response.1.files.unwrap_or_default();
+        if response.1.next_page_token.is_some() {
+            files.append(
+                &mut self
+                    .fetch_files(drive_id, response.1.next_page_token)
+                    .await?,
+            );
+        }
+        Ok(files)
+    }
+
+    /// Lists a corpus, reusing the previous listing if its change token shows nothing has changed.
+    async fn list_corpus(
+        &self,
+        shared_drive: Option<&SharedDriveConfig>,
+    ) -> Result<Vec<RemoteFile>> {
+        let drive_id = shared_drive.map(|shared_drive| shared_drive.id.clone());
+        let previous = self.corpora.borrow().get(&drive_id).cloned();
+        if let Some(previous) = previous {
+            if let Some(page_token) = self
+                .get_unchanged_page_token(drive_id.as_deref(), &previous.page_token)
+                .await?
+            {
+                debug!(
+                    "No changes to {}",
+                    drive_id.as_deref().unwrap_or("My Drive")
+                );
+                let files = previous.files.clone();
+                self.corpora.borrow_mut().insert(
+                    drive_id,
+                    Corpus {
+                        page_token,
+                        files: previous.files,
+                    },
+                );
+                return Ok(files);
+            }
+        }
+        let page_token = self.get_start_page_token(drive_id.as_deref()).await?;
+        let mut files = Vec::new();
+        for file in self.fetch_files(drive_id.as_deref(), None).await? {
+            match GoogleDrive::convert_to_remote_file(&file) {
+                Ok(remote_file) => files.push(remote_file),
+                Err(error) => error!("Skipping remote file. {}", error),
+            }
+        }
+        if let Some(shared_drive) = shared_drive {
+            files.push(self.get_shared_drive_root(shared_drive).await?);
+        }
+        self.corpora.borrow_mut().insert(
+            drive_id,
+            Corpus {
+                page_token,
+                files: files.clone(),
+            },
+        );
+        Ok(files)
+    }
+
+    async fn get_start_page_token(&self, drive_id: Option<&str>) -> Result<String> {
+        let mut start_page_token_call = self
+            .hub
+            .changes()
+            .get_start_page_token()
+            .add_scope(Scope::Full)
+            .supports_all_drives(true);
+        if let Some(drive_id) = drive_id {
+            start_page_token_call = start_page_token_call.drive_id(drive_id);
+        }
+        let response = start_page_token_call.doit().await?;
+        response
+            .1
+            .start_page_token
+            .ok_or_else(|| RdriveError::Api("Missing start page token".to_string()))
+    }
+
+    /// Returns the token to use next time if nothing has changed since `page_token`.
+    async fn get_unchanged_page_token(
+        &self,
+        drive_id: Option<&str>,
+        page_token: &str,
+    ) -> Result<Option<String>> {
+        let mut change_list_call = self
+            .hub
+            .changes()
+            .list(page_token)
+            .add_scope(Scope::Full)
+            .supports_all_drives(true)
+            .param(
+                "fields",
+                "nextPageToken, newStartPageToken, changes(fileId)",
+            );
+        if let Some(drive_id) = drive_id {
+            change_list_call = change_list_call
+                .drive_id(drive_id)
+                .include_items_from_all_drives(true);
+        }
+        let response = change_list_call.doit().await?;
+        if response.1.changes.unwrap_or_default().is_empty() && response.1.next_page_token.is_none()
+        {
+            return Ok(response.1.new_start_page_token);
+        }
+        Ok(None)
+    }
+
+    /// The root of a shared drive isn't listed as a file, so it's added as a folder named after
+    /// the drive's directory for the drive's files to be synced into.
+    async fn get_shared_drive_root(&self, shared_drive: &SharedDriveConfig) -> Result<RemoteFile> {
+        let response = self
+            .hub
+            .drives()
+            .get(&shared_drive.id)
+            .add_scope(Scope::Full)
+            .param("fields", "id, name, createdTime")
+            .doit()
+            .await?;
+        let name = match &shared_drive.directory {
+            Some(directory) => directory.clone(),
+            None => response.1.name.ok_or_else(|| {
+                RdriveError::Api(format!("Shared drive {} is missing name", shared_drive.id))
+            })?,
+        };
+        Ok(RemoteFile {
+            id: shared_drive.id.clone(),
+            name,
+            mime_type: DIRECTORY_MIME_TYPE.to_string(),
+            parents: Vec::new(),
+            web_view_link: None,
+            owned_by_me: true,
+            modified_time: response
+                .1
+                .created_time
+                .ok_or_else(|| {
+                    RdriveError::Api(format!(
+                        "Shared drive {} is missing createdTime",
+                        shared_drive.id
+                    ))
+                })?
+                .into(),
+            trashed: false,
+            version: None,
+            can_edit: true,
+            shortcut_target_id: None,
+            permissions: Permissions::default(),
+            symlink_target: None,
+            size: None,
+            md5_checksum: None,
+        })
+    }
+
+    async fn create(&self, file: File, file_wrapper: &FileWrapper) -> Result<RemoteFile> {
+        let file = File {
+            modified_time: Some(GoogleDrive::get_local_modified_time(file_wrapper)?),
+            app_properties: GoogleDrive::get_app_properties(file_wrapper),
+            ..file
+        };
+        let response = self
+            .hub
+            .files()
+            .create(file)
+            .add_scope(Scope::Full)
+            .supports_all_drives(true)
+            .param("fields", FILE_FIELDS)
+            .upload(
+                GoogleDrive::open_content(file_wrapper)?,
+                file_wrapper.mime_type.parse()?,
+            )
+            .await?;
+        GoogleDrive::convert_to_remote_file(&response.1)
+    }
+
+    /// Sent with uploads so the remote keeps the time the file was last edited locally rather
+    /// than the time it was uploaded.
+    fn get_local_modified_time(file_wrapper: &FileWrapper) -> Result<DateTime<Utc>> {
+        let metadata = if file_wrapper.symlink_target.is_some() {
+            fs::symlink_metadata(&file_wrapper.path)?
+        } else {
+            fs::metadata(&file_wrapper.path)?
+        };
+        Ok(metadata.modified()?.into())
+    }
+
+    /// Left unset when there are no permissions to store, so permissions stored by another
+    /// device are kept.
+    fn get_app_properties(file_wrapper: &FileWrapper) -> Option<HashMap<String, String>> {
+        let mut properties = file_wrapper.permissions.to_properties();
+        if let Some(symlink_target) = &file_wrapper.symlink_target {
+            properties.insert(SYMLINK_TARGET_PROPERTY.to_string(), symlink_target.clone());
+        }
+        if properties.is_empty() {
+            None
+        } else {
+            Some(properties)
+        }
+    }
+
+    /// Stored symlinks are uploaded with their target as their content, rather than the content
+    /// of the file they point to.
+    fn open_content(file_wrapper: &FileWrapper) -> Result<Box<dyn ReadSeek>> {
+        Ok(match &file_wrapper.symlink_target {
+            Some(symlink_target) => Box::new(Cursor::new(symlink_target.clone().into_bytes())),
+            None => Box::new(fs::File::open(&file_wrapper.path)?),
+        })
+    }
+
+    fn convert_to_revision(revision: drive3::api::Revision) -> Result<Revision> {
+        let id = revision
+            .id
+            .ok_or_else(|| RdriveError::Api("Revision is missing id".to_string()))?;
+        Ok(Revision {
+            modified_time: revision
+                .modified_time
+                .ok_or_else(|| {
+                    RdriveError::Api(format!("Revision {} is missing modifiedTime", id))
+                })?
+                .into(),
+            id,
+            size: revision.size.map(|size| size as u64),
+            author: revision
+                .last_modifying_user
+                .and_then(|user| user.display_name),
+            md5_checksum: revision.md5_checksum,
+            keep_forever: revision.keep_forever.unwrap_or(false),
+        })
+    }
+
+    fn convert_to_remote_file(file: &File) -> Result<RemoteFile> {
+        let missing = |field: &str| {
+            RdriveError::Api(format!(
+                "File {} is missing {}",
+                file.id.clone().unwrap_or_default(),
+                field
+            ))
+        };
+        Ok(RemoteFile {
+            id: file.id.clone().ok_or_else(|| missing("id"))?,
+            name: file.name.clone().ok_or_else(|| missing("name"))?,
+            mime_type: file.mime_type.clone().ok_or_else(|| missing("mimeType"))?,
+            parents: file.parents.clone().unwrap_or_default(),
+            web_view_link: file.web_view_link.clone(),
+            // Files in shared drives belong to the drive rather than a user, so are synced as if
+            // they were owned by the user.
+            owned_by_me: file.owned_by_me.unwrap_or(true) || file.drive_id.is_some(),
+            modified_time: file
+                .modified_time
+                .ok_or_else(|| missing("modifiedTime"))?
+                .into(),
+            trashed: file.trashed.unwrap_or(false),
+            version: file.version.map(|version| version.to_string()),
+            can_edit: file
+                .capabilities
+                .as_ref()
+                .and_then(|capabilities| capabilities.can_edit)
+                .unwrap_or(true),
+            shortcut_target_id: file
+                .shortcut_details
+                .as_ref()
+                .and_then(|shortcut_details| shortcut_details.target_id.clone()),
+            permissions: file
+                .app_properties
+                .as_ref()
+                .map(Permissions::from_properties)
+                .unwrap_or_default(),
+            symlink_target: file
+                .app_properties
+                .as_ref()
+                .and_then(|properties| properties.get(SYMLINK_TARGET_PROPERTY).cloned()),
+            size: file.size.map(|size| size as u64),
+            md5_checksum: file.md5_checksum.clone(),
+        })
+    }
+}
+
+#[async_trait(?Send)]
+impl Remote for GoogleDrive {
+    async fn list_files(&self) -> Result<Vec<RemoteFile>> {
+        let mut files = self.list_corpus(None).await?;
+        for shared_drive in &self.shared_drives {
+            match self.list_corpus(Some(shared_drive)).await {
+                Ok(mut shared_drive_files) => files.append(&mut shared_drive_files),
+                Err(error) => error!("Skipping shared drive {}. {}", shared_drive.id, error),
+            }
+        }
+        Ok(files)
+    }
+
+    async fn download(&self, file_wrapper: &FileWrapper) -> Result<Vec<u8>> {
+        let response = self
+            .hub
+            .files()
+            .get(file_wrapper.id.as_ref())
+            .param("alt", "media")
+            .add_scope(Scope::Full)
+            .supports_all_drives(true)
+            .doit()
+            .await?;
+        let bytes = to_bytes(response.0.into_body())
+            .await
+            .ok_or_else(|| RdriveError::Api("Failed to read response body".to_string()))?;
+        Ok(bytes.to_vec())
+    }
+
+    async fn export(&self, file_wrapper: &FileWrapper, mime_type: &str) -> Result<Vec<u8>> {
+        let response = self
+            .hub
+            .files()
+            .export(file_wrapper.id.as_ref(), mime_type)
+            .add_scope(Scope::Full)
+            .doit()
+            .await?;
+        let bytes = to_bytes(response.into_body())
+            .await
+            .ok_or_else(|| RdriveError::Api("Failed to read response body".to_string()))?;
+        Ok(bytes.to_vec())
+    }
+
+    async fn upload(
+        &self,
+        file_wrapper: &FileWrapper,
+        parent_id: Option<&str>,
+    ) -> Result<RemoteFile> {
+        let mime_type = if file_wrapper.directory {
+            Some(DIRECTORY_MIME_TYPE.to_string())
+        } else {
+            Some(file_wrapper.mime_type.clone())
+        };
+        let file = File {
+            mime_type,
+            parents: parent_id.map(|parent_id| vec![parent_id.to_string()]),
+            name: Some(file_wrapper.name.clone()),
+            ..Default::default()
+        };
+        self.create(file, file_wrapper).await
+    }
+
+    /// Drive converts the upload when the file's MIME type differs from the uploaded content's.
+    async fn import(
+        &self,
+        file_wrapper: &FileWrapper,
+        parent_id: Option<&str>,
+        mime_type: &str,
+    ) -> Result<RemoteFile> {
+        let name = Path::new(&file_wrapper.name)
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .unwrap_or(&file_wrapper.name);
+        let file = File {
+            mime_type: Some(mime_type.to_string()),
+            parents: parent_id.map(|parent_id| vec![parent_id.to_string()]),
+            name: Some(name.to_string()),
+            ..Default::default()
+        };
+        self.create(file, file_wrapper).await
+    }
+
+    async fn update(&self, file_wrapper: &FileWrapper) -> Result<RemoteFile> {
+        let file = File {
+            modified_time: Some(GoogleDrive::get_local_modified_time(file_wrapper)?),
+            app_properties: GoogleDrive::get_app_properties(file_wrapper),
+            ..Default::default()
+        };
+        let response = self
+            .hub
+            .files()
+            .update(file, file_wrapper.id.as_ref())
+            .add_scope(Scope::Full)
+            .supports_all_drives(true)
+            .param("fields", FILE_FIELDS)
+            .upload(
+                GoogleDrive::open_content(file_wrapper)?,
+                file_wrapper.mime_type.parse()?,
+            )
+            .await?;
+        GoogleDrive::convert_to_remote_file(&response.1)
+    }
+
+    async fn move_file(
+        &self,
+        file_wrapper: &FileWrapper,
+        parent_id: Option<&str>,
+        name: &str,
+    ) -> Result<RemoteFile> {
+        let current = self
+            .hub
+            .files()
+            .get(file_wrapper.id.as_ref())
+            .add_scope(Scope::Full)
+            .supports_all_drives(true)
+            .param("fields", "parents")
+            .doit()
+            .await?;
+        let current_parents = current.1.parents.unwrap_or_default().join(",");
+        let file = File {
+            name: Some(name.to_string()),
+            ..Default::default()
+        };
+        let mut update_call = self
+            .hub
+            .files()
+            .update(file, file_wrapper.id.as_ref())
+            .add_scope(Scope::Full)
+            .supports_all_drives(true)
+            .param("fields", FILE_FIELDS);
+        if let Some(parent_id) = parent_id {
+            update_call = update_call
+                .add_parents(parent_id)
+                .remove_parents(&current_parents);
+        }
+        let response = update_call.doit_without_upload().await?;
+        GoogleDrive::convert_to_remote_file(&response.1)
+    }
+
+    /// Google Drive files are moved to the bin rather than being permanently deleted.
+    async fn trash(&self, file_wrapper: &FileWrapper) -> Result<()> {
+        let file = File {
+            trashed: Some(true),
+            ..Default::default()
+        };
+        self.hub
+            .files()
+            .update(file, file_wrapper.id.as_ref())
+            .add_scope(Scope::Full)
+            .supports_all_drives(true)
+            .doit_without_upload()
+            .await?;
+        Ok(())
+    }
+
+    async fn list_revisions(&self, file_wrapper: &FileWrapper) -> Result<Vec<Revision>> {
+        let mut revisions = Vec::new();
+        let mut page_token: Option<String> = None;
+        loop {
+            let mut revision_list_call = self
+                .hub
+                .revisions()
+                .list(file_wrapper.id.as_ref())
+                .add_scope(Scope::Full)
+                .param("fields", REVISION_LIST_FIELDS);
+            if let Some(token) = &page_token {
+                revision_list_call = revision_list_call.page_token(token);
+            }
+            let response = revision_list_call.doit().await?;
+            for revision in response.1.revisions.unwrap_or_default() {
+                revisions.push(GoogleDrive::convert_to_revision(revision)?);
+            }
+            page_token = response.1.next_page_token;
+            if page_token.is_none() {
+                return Ok(revisions);
+            }
+        }
+    }
+
+    async fn download_revision(
+        &self,
+        file_wrapper: &FileWrapper,
+        revision_id: &str,
+    ) -> Result<Vec<u8>> {
+        let response = self
+            .hub
+            .revisions()
+            .get(file_wrapper.id.as_ref(), revision_id)
+            .param("alt", "media")
+            .add_scope(Scope::Full)
+            .doit()
+            .await?;
+        let bytes = to_bytes(response.0.into_body())
+            .await
+            .ok_or_else(|| RdriveError::Api("Failed to read response body".to_string()))?;
+        Ok(bytes.to_vec())
+    }
+
+    async fn keep_forever(&self, id: &str) -> Result<()> {
+        let response = self
+            .hub
+            .files()
+            .get(id)
+            .add_scope(Scope::Full)
+            .supports_all_drives(true)
+            .param("fields", "headRevisionId")
+            .doit()
+            .await?;
+        let head_revision_id = response
+            .1
+            .head_revision_id
+            .ok_or_else(|| RdriveError::Api(format!("File {} has no revisions to keep", id)))?;
+        let revision = drive3::api::Revision {
+            keep_forever: Some(true),
+            ..Default::default()
+        };
+        self.hub
+            .revisions()
+            .update(revision, id, &head_revision_id)
+            .add_scope(Scope::Full)
+            .doit()
+            .await?;
+        Ok(())
+    }
+}
+
+pub async fn get_authenticator() -> Result<Authenticator<HttpsConnector<HttpConnector>>> {
+    let secret: ApplicationSecret = yup_oauth2::read_application_secret("../secret.json")
+        .await
+        .map_err(|error| RdriveError::Auth(format!("Failed to read secret.json. {}", error)))?;
+    let token_file = get_base_data_path()?.join("temp-key");
+    let connector = hyper_rustls::HttpsConnectorBuilder::new()
+        .with_native_roots()?
+        .https_only()
+        .enable_http2()
+        .build();
+    let executor = hyper_util::rt::TokioExecutor::new();
+    InstalledFlowAuthenticator::with_client(
+        secret,
+        InstalledFlowReturnMethod::Interactive,
+        CustomHyperClientBuilder::from(Client::builder(executor).build(connector)),
+    )
+    .persist_tokens_to_disk(token_file)
+    .build()
+    .await
+    .map_err(|error| RdriveError::Auth(error.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::time::{Duration, SystemTime};
+
+    use super::*;
+
+    #[test]
+    fn get_local_modified_time_should_read_local_file() {
+        let path = env::temp_dir().join("rdrive-google-mtime-test.txt");
+        fs::write(&path, "rdrive").unwrap();
+        let modified = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        fs::File::options()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_modified(modified)
+            .unwrap();
+        let file_wrapper = FileWrapper {
+            path,
+            ..FileWrapper::test_default()
+        };
+
+        let modified_time = GoogleDrive::get_local_modified_time(&file_wrapper).unwrap();
+        assert_eq!(modified_time, DateTime::<Utc>::from(modified));
+    }
+}
@@ -0,0 +1,632 @@
+use std::collections::{HashSet, VecDeque};
+use std::fs;
+
+use async_trait::async_trait;
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use chrono::DateTime;
+use drive3::common::{to_body, to_bytes, Body};
+use drive3::hyper::header::{AUTHORIZATION, CONTENT_TYPE};
+use drive3::hyper::{Method, Request, Uri};
+use drive3::hyper_rustls::HttpsConnector;
+use drive3::hyper_util::client::legacy::connect::HttpConnector;
+use drive3::hyper_util::client::legacy::Client;
+use log::debug;
+use percent_encoding::{percent_decode_str, utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+
+use crate::drive::{as_secs, FileWrapper, DIRECTORY_MIME_TYPE};
+use crate::error::{RdriveError, Result};
+use crate::permissions::Permissions;
+use crate::remote::{Remote, RemoteFile, Revision};
+
+const PROPFIND_BODY: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<d:propfind xmlns:d="DAV:">
+  <d:prop>
+    <d:resourcetype/>
+    <d:getlastmodified/>
+    <d:getetag/>
+    <d:getcontenttype/>
+    <d:getcontentlength/>
+  </d:prop>
+</d:propfind>"#;
+
+const PATH_SEGMENT: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'.')
+    .remove(b'_')
+    .remove(b'~');
+
+/// A remote backed by a WebDAV collection, such as a Nextcloud user's files.
+///
+/// Files are identified by their (percent encoded) href on the server, so the parent of a file is
+/// the href of the collection containing it.
+pub struct WebDav {
+    client: Client<HttpsConnector<HttpConnector>, Body>,
+    origin: String,
+    root_href: String,
+    authorization: String,
+}
+
+impl WebDav {
+    pub fn new(
+        client: Client<HttpsConnector<HttpConnector>, Body>,
+        url: &str,
+        username: &str,
+        password: &str,
+    ) -> Result<WebDav> {
+        let uri: Uri = url.parse()?;
+        let scheme = uri
+            .scheme_str()
+            .ok_or_else(|| RdriveError::Config("WebDAV url is missing a scheme".to_string()))?;
+        let authority = uri
+            .authority()
+            .ok_or_else(|| RdriveError::Config("WebDAV url is missing a host".to_string()))?;
+        Ok(WebDav {
+            client,
+            origin: format!("{}://{}", scheme, authority),
+            root_href: WebDav::as_collection_href(&WebDav::normalise_href(uri.path())?),
+            authorization: format!(
+                "Basic {}",
+                STANDARD.encode(format!("{}:{}", username, password))
+            ),
+        })
+    }
+
+    async fn request(
+        &self,
+        method: Method,
+        href: &str,
+        headers: Vec<(&str, String)>,
+        body: Option<Vec<u8>>,
+    ) -> Result<Vec<u8>> {
+        let mut builder = Request::builder()
+            .method(method.clone())
+            .uri(format!("{}{}", self.origin, href))
+            .header(AUTHORIZATION, &self.authorization);
+        for (name, value) in headers {
+            builder = builder.header(name, value);
+        }
+        let response = self.client.request(builder.body(to_body(body))?).await?;
+        let status = response.status();
+        let bytes = to_bytes(response.into_body())
+            .await
+            .ok_or_else(|| RdriveError::Api("Failed to read response body".to_string()))?;
+        if !status.is_success() {
+            return Err(RdriveError::Api(format!(
+                "{} {} failed with status {}",
+                method, href, status
+            )));
+        }
+        Ok(bytes.to_vec())
+    }
+
+    async fn propfind(&self, href: &str, depth: &str) -> Result<Vec<RemoteFile>> {
+        let body = self
+            .request(
+                Method::from_bytes(b"PROPFIND")?,
+                href,
+                vec![
+                    ("Depth", depth.to_string()),
+                    (CONTENT_TYPE.as_str(), "application/xml".to_string()),
+                ],
+                Some(PROPFIND_BODY.as_bytes().to_vec()),
+            )
+            .await?;
+        WebDav::parse_multistatus(&String::from_utf8(body)?)
+    }
+
+    async fn stat(&self, href: &str) -> Result<RemoteFile> {
+        self.propfind(href, "0")
+            .await?
+            .into_iter()
+            .next()
+            .ok_or_else(|| RdriveError::Api(format!("No properties returned for {}", href)))
+    }
+
+    fn child_href(&self, parent_id: Option<&str>, name: &str, directory: bool) -> String {
+        let parent = parent_id.unwrap_or(&self.root_href);
+        let href = format!(
+            "{}{}",
+            WebDav::as_collection_href(parent),
+            utf8_percent_encode(name, PATH_SEGMENT)
+        );
+        if directory {
+            WebDav::as_collection_href(&href)
+        } else {
+            href
+        }
+    }
+
+    fn as_collection_href(href: &str) -> String {
+        if href.ends_with('/') {
+            href.to_string()
+        } else {
+            format!("{}/", href)
+        }
+    }
+
+    /// Servers differ in how they write hrefs, some returning absolute URLs or encoding
+    /// characters differently, so hrefs are reduced to a path with every segment encoded the same
+    /// way before they are used as IDs.
+    fn normalise_href(href: &str) -> Result<String> {
+        let path = match href.find("://") {
+            Some(index) => {
+                let after_scheme = &href[index + 3..];
+                after_scheme
+                    .find('/')
+                    .map_or("/", |path_index| &after_scheme[path_index..])
+            }
+            None => href,
+        };
+        let segments = path
+            .split('/')
+            .map(|segment| {
+                percent_decode_str(segment)
+                    .decode_utf8()
+                    .map(|segment| utf8_percent_encode(&segment, PATH_SEGMENT).to_string())
+            })
+            .collect::<std::result::Result<Vec<String>, _>>()?;
+        let normalised = segments.join("/");
+        if normalised.starts_with('/') {
+            Ok(normalised)
+        } else {
+            Ok(format!("/{}", normalised))
+        }
+    }
+
+    fn parent_href(href: &str) -> String {
+        let trimmed = href.trim_end_matches('/');
+        match trimmed.rfind('/') {
+            Some(index) => trimmed[..=index].to_string(),
+            None => "/".to_string(),
+        }
+    }
+
+    /// WebDAV has no standard way to set a file's modified time, so the `X-OC-Mtime` header
+    /// understood by Nextcloud and ownCloud is sent. Other servers ignore it.
+    fn get_upload_headers(file_wrapper: &FileWrapper) -> Result<Vec<(&'static str, String)>> {
+        if file_wrapper.symlink_target.is_some() {
+            return Err(RdriveError::Path(
+                file_wrapper.path.clone(),
+                "Symlinks can't be stored on WebDAV".to_string(),
+            ));
+        }
+        let modified = fs::metadata(&file_wrapper.path)?.modified()?;
+        Ok(vec![
+            (CONTENT_TYPE.as_str(), file_wrapper.mime_type.clone()),
+            ("X-OC-Mtime", as_secs(modified).to_string()),
+        ])
+    }
+
+    fn no_revisions(file_wrapper: &FileWrapper) -> RdriveError {
+        RdriveError::Api(format!(
+            "{} has no revisions as WebDAV doesn't keep them",
+            file_wrapper.id
+        ))
+    }
+
+    fn parse_multistatus(body: &str) -> Result<Vec<RemoteFile>> {
+        let document = roxmltree::Document::parse(body)?;
+        let mut files = Vec::new();
+        for response in document
+            .descendants()
+            .filter(|node| node.has_tag_name(("DAV:", "response")))
+        {
+            // Properties the server couldn't return come back in a propstat of their own, with a
+            // status such as 404, so only those in a 200 propstat are real.
+            let ok_propstats: Vec<roxmltree::Node> = response
+                .children()
+                .filter(|node| node.has_tag_name(("DAV:", "propstat")))
+                .filter(|propstat| {
+                    propstat
+                        .children()
+                        .find(|node| node.has_tag_name(("DAV:", "status")))
+                        .and_then(|status| status.text())
+                        .and_then(|status| status.split_whitespace().nth(1))
+                        == Some("200")
+                })
+                .collect();
+            if ok_propstats.is_empty() {
+                continue;
+            }
+            let property = |name: &str| -> Option<String> {
+                ok_propstats
+                    .iter()
+                    .flat_map(|propstat| propstat.descendants())
+                    .find(|node| node.has_tag_name(("DAV:", name)))
+                    .and_then(|node| node.text())
+                    .map(|text| text.trim().to_string())
+            };
+            let href = response
+                .children()
+                .find(|node| node.has_tag_name(("DAV:", "href")))
+                .and_then(|node| node.text())
+                .ok_or_else(|| RdriveError::Api("WebDAV response is missing href".to_string()))?;
+            let href = WebDav::normalise_href(href.trim())?;
+            let directory = ok_propstats
+                .iter()
+                .flat_map(|propstat| propstat.descendants())
+                .any(|node| node.has_tag_name(("DAV:", "collection")));
+            let name = href
+                .trim_end_matches('/')
+                .rsplit('/')
+                .next()
+                .unwrap_or_default();
+            let modified_time = match property("getlastmodified") {
+                Some(last_modified) => DateTime::parse_from_rfc2822(&last_modified)?,
+                None => DateTime::UNIX_EPOCH.into(),
+            };
+            let mime_type = if directory {
+                DIRECTORY_MIME_TYPE.to_string()
+            } else {
+                property("getcontenttype")
+                    .unwrap_or_else(|| mime::APPLICATION_OCTET_STREAM.to_string())
+            };
+            files.push(RemoteFile {
+                id: if directory {
+                    WebDav::as_collection_href(&href)
+                } else {
+                    href.clone()
+                },
+                name: percent_decode_str(name).decode_utf8()?.to_string(),
+                mime_type,
+                parents: vec![WebDav::parent_href(&href)],
+                web_view_link: None,
+                owned_by_me: true,
+                modified_time,
+                trashed: false,
+                version: property("getetag"),
+                can_edit: true,
+                shortcut_target_id: None,
+                permissions: Permissions::default(),
+                symlink_target: None,
+                size: property("getcontentlength").and_then(|length| length.parse().ok()),
+                md5_checksum: None,
+            });
+        }
+        Ok(files)
+    }
+}
+
+#[async_trait(?Send)]
+impl Remote for WebDav {
+    async fn list_files(&self) -> Result<Vec<RemoteFile>> {
+        let mut files = Vec::new();
+        let mut collections = VecDeque::from(vec![self.root_href.clone()]);
+        let mut seen = HashSet::from([self.root_href.clone()]);
+        while let Some(collection) = collections.pop_front() {
+            debug!("Listing WebDAV collection {}", collection);
+            for file in self.propfind(&collection, "1").await? {
+                if !seen.insert(file.id.clone()) {
+                    continue;
+                }
+                if file.mime_type == DIRECTORY_MIME_TYPE {
+                    collections.push_back(file.id.clone());
+                }
+                files.push(file);
+            }
+        }
+        Ok(files)
+    }
+
+    async fn download(&self, file_wrapper: &FileWrapper) -> Result<Vec<u8>> {
+        self.request(Method::GET, &file_wrapper.id, vec![], None)
+            .await
+    }
+
+    async fn export(&self, file_wrapper: &FileWrapper, _mime_type: &str) -> Result<Vec<u8>> {
+        Err(RdriveError::Api(format!(
+            "{} can't be exported as WebDAV has no native documents",
+            file_wrapper.id
+        )))
+    }
+
+    async fn upload(
+        &self,
+        file_wrapper: &FileWrapper,
+        parent_id: Option<&str>,
+    ) -> Result<RemoteFile> {
+        let href = self.child_href(parent_id, &file_wrapper.name, file_wrapper.directory);
+        if file_wrapper.directory {
+            self.request(Method::from_bytes(b"MKCOL")?, &href, vec![], None)
+                .await?;
+        } else {
+            self.request(
+                Method::PUT,
+                &href,
+                WebDav::get_upload_headers(file_wrapper)?,
+                Some(fs::read(&file_wrapper.path)?),
+            )
+            .await?;
+        }
+        self.stat(&href).await
+    }
+
+    async fn import(
+        &self,
+        file_wrapper: &FileWrapper,
+        _parent_id: Option<&str>,
+        _mime_type: &str,
+    ) -> Result<RemoteFile> {
+        Err(RdriveError::Api(format!(
+            "{} can't be imported as WebDAV has no native documents",
+            file_wrapper.path.display()
+        )))
+    }
+
+    async fn update(&self, file_wrapper: &FileWrapper) -> Result<RemoteFile> {
+        self.request(
+            Method::PUT,
+            &file_wrapper.id,
+            WebDav::get_upload_headers(file_wrapper)?,
+            Some(fs::read(&file_wrapper.path)?),
+        )
+        .await?;
+        self.stat(&file_wrapper.id).await
+    }
+
+    async fn move_file(
+        &self,
+        file_wrapper: &FileWrapper,
+        parent_id: Option<&str>,
+        name: &str,
+    ) -> Result<RemoteFile> {
+        let parent_href = match parent_id {
+            Some(parent_id) => parent_id.to_string(),
+            None => WebDav::parent_href(&file_wrapper.id),
+        };
+        let href = self.child_href(Some(&parent_href), name, file_wrapper.directory);
+        self.request(
+            Method::from_bytes(b"MOVE")?,
+            &file_wrapper.id,
+            vec![
+                ("Destination", format!("{}{}", self.origin, href)),
+                ("Overwrite", "F".to_string()),
+            ],
+            None,
+        )
+        .await?;
+        self.stat(&href).await
+    }
+
+    /// WebDAV has no standard trash, so this relies on the server keeping deleted files, as the
+    /// trash bin of Nextcloud and ownCloud does.
+    async fn trash(&self, file_wrapper: &FileWrapper) -> Result<()> {
+        self.request(Method::DELETE, &file_wrapper.id, vec![], None)
+            .await?;
+        Ok(())
+    }
+
+    async fn list_revisions(&self, file_wrapper: &FileWrapper) -> Result<Vec<Revision>> {
+        Err(WebDav::no_revisions(file_wrapper))
+    }
+
+    async fn download_revision(
+        &self,
+        file_wrapper: &FileWrapper,
+        _revision_id: &str,
+    ) -> Result<Vec<u8>> {
+        Err(WebDav::no_revisions(file_wrapper))
+    }
+
+    async fn keep_forever(&self, id: &str) -> Result<()> {
+        Err(RdriveError::Api(format!(
+            "Revisions of {} can't be kept as WebDAV doesn't keep revisions",
+            id
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::path::PathBuf;
+    use std::time::{Duration, SystemTime};
+
+    use chrono::{DateTime, FixedOffset};
+    use drive3::hyper_util;
+
+    use super::*;
+
+    const MULTISTATUS: &str = r#"<?xml version="1.0"?>
+<d:multistatus xmlns:d="DAV:" xmlns:oc="http://owncloud.org/ns">
+  <d:response>
+    <d:href>/remote.php/dav/files/user/</d:href>
+    <d:propstat>
+      <d:prop>
+        <d:resourcetype><d:collection/></d:resourcetype>
+        <d:getlastmodified>Tue, 01 Oct 2024 10:00:00 GMT</d:getlastmodified>
+        <d:getetag>"root"</d:getetag>
+      </d:prop>
+      <d:status>HTTP/1.1 200 OK</d:status>
+    </d:propstat>
+  </d:response>
+  <d:response>
+    <d:href>/remote.php/dav/files/user/My%20Documents/</d:href>
+    <d:propstat>
+      <d:prop>
+        <d:resourcetype><d:collection/></d:resourcetype>
+        <d:getlastmodified>Tue, 01 Oct 2024 11:00:00 GMT</d:getlastmodified>
+        <d:getetag>"folder"</d:getetag>
+      </d:prop>
+      <d:status>HTTP/1.1 200 OK</d:status>
+    </d:propstat>
+    <d:propstat>
+      <d:prop>
+        <d:getcontenttype>text/plain</d:getcontenttype>
+        <d:getcontentlength>4096</d:getcontentlength>
+      </d:prop>
+      <d:status>HTTP/1.1 404 Not Found</d:status>
+    </d:propstat>
+  </d:response>
+  <d:response>
+    <d:href>https://cloud.example.com/remote.php/dav/files/user/My Documents/report.pdf</d:href>
+    <d:propstat>
+      <d:prop>
+        <d:resourcetype/>
+        <d:getlastmodified>Wed, 02 Oct 2024 12:30:00 GMT</d:getlastmodified>
+        <d:getetag>"abc123"</d:getetag>
+        <d:getcontenttype>application/pdf</d:getcontenttype>
+        <d:getcontentlength>2048</d:getcontentlength>
+      </d:prop>
+      <d:status>HTTP/1.1 200 OK</d:status>
+    </d:propstat>
+  </d:response>
+</d:multistatus>"#;
+
+    #[test]
+    fn parse_multistatus_should_convert_responses_to_remote_files() {
+        let files = WebDav::parse_multistatus(MULTISTATUS).unwrap();
+        assert_eq!(files.len(), 3);
+        assert_eq!(
+            files[1],
+            RemoteFile {
+                id: "/remote.php/dav/files/user/My%20Documents/".to_string(),
+                name: "My Documents".to_string(),
+                mime_type: DIRECTORY_MIME_TYPE.to_string(),
+                parents: vec!["/remote.php/dav/files/user/".to_string()],
+                web_view_link: None,
+                owned_by_me: true,
+                modified_time: DateTime::parse_from_rfc3339("2024-10-01T11:00:00+00:00").unwrap(),
+                trashed: false,
+                version: Some("\"folder\"".to_string()),
+                can_edit: true,
+                shortcut_target_id: None,
+                permissions: Permissions::default(),
+                symlink_target: None,
+                size: None,
+                md5_checksum: None,
+            }
+        );
+        assert_eq!(
+            files[2],
+            RemoteFile {
+                id: "/remote.php/dav/files/user/My%20Documents/report.pdf".to_string(),
+                name: "report.pdf".to_string(),
+                mime_type: "application/pdf".to_string(),
+                parents: vec!["/remote.php/dav/files/user/My%20Documents/".to_string()],
+                web_view_link: None,
+                owned_by_me: true,
+                modified_time: DateTime::parse_from_rfc3339("2024-10-02T12:30:00+00:00").unwrap(),
+                trashed: false,
+                version: Some("\"abc123\"".to_string()),
+                can_edit: true,
+                shortcut_target_id: None,
+                permissions: Permissions::default(),
+                symlink_target: None,
+                size: Some(2048),
+                md5_checksum: None,
+            }
+        );
+    }
+
+    #[test]
+    fn parse_multistatus_should_error_on_invalid_xml() {
+        assert!(WebDav::parse_multistatus("<d:multistatus").is_err());
+    }
+
+    #[test]
+    fn normalise_href_should_strip_origin_and_encode_segments_the_same_way() {
+        assert_eq!(
+            WebDav::normalise_href("https://cloud.example.com/dav/My Documents/").unwrap(),
+            "/dav/My%20Documents/"
+        );
+        assert_eq!(
+            WebDav::normalise_href("/dav/My%20Documents/a%2Db.txt").unwrap(),
+            "/dav/My%20Documents/a-b.txt"
+        );
+        assert_eq!(
+            WebDav::normalise_href("https://cloud.example.com").unwrap(),
+            "/"
+        );
+    }
+
+    #[test]
+    fn parent_href_should_strip_last_segment() {
+        assert_eq!(WebDav::parent_href("/dav/a/b.txt"), "/dav/a/");
+        assert_eq!(WebDav::parent_href("/dav/a/"), "/dav/");
+        assert_eq!(WebDav::parent_href("/"), "/");
+    }
+
+    /// Runs against a local WebDAV server, for example
+    /// `docker run -p 8080:80 -e USERNAME=user -e PASSWORD=pass bytemark/webdav`, with
+    /// `RDRIVE_WEBDAV_URL`, `RDRIVE_WEBDAV_USERNAME` and `RDRIVE_WEBDAV_PASSWORD` set.
+    #[test]
+    fn get_upload_headers_should_send_local_modified_time() {
+        let path = env::temp_dir().join("rdrive-webdav-mtime-test.txt");
+        fs::write(&path, "rdrive").unwrap();
+        let modified = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        fs::File::options()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_modified(modified)
+            .unwrap();
+        let file_wrapper = FileWrapper {
+            path,
+            ..FileWrapper::test_default()
+        };
+
+        let headers = WebDav::get_upload_headers(&file_wrapper).unwrap();
+        assert!(headers.contains(&("X-OC-Mtime", "1700000000".to_string())));
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn webdav_should_round_trip_against_local_server() {
+        let client = Client::builder(hyper_util::rt::TokioExecutor::new()).build(
+            drive3::hyper_rustls::HttpsConnectorBuilder::new()
+                .with_native_roots()
+                .unwrap()
+                .https_or_http()
+                .enable_http2()
+                .build(),
+        );
+        let webdav = WebDav::new(
+            client,
+            &env::var("RDRIVE_WEBDAV_URL").unwrap(),
+            &env::var("RDRIVE_WEBDAV_USERNAME").unwrap(),
+            &env::var("RDRIVE_WEBDAV_PASSWORD").unwrap(),
+        )
+        .unwrap();
+        let path = env::temp_dir().join("rdrive-webdav-test.txt");
+        fs::write(&path, "rdrive").unwrap();
+        let file_wrapper = FileWrapper {
+            id: String::new(),
+            name: "rdrive-webdav-test.txt".to_string(),
+            mime_type: "text/plain".to_string(),
+            path: PathBuf::from(&path),
+            directory: false,
+            web_view_link: None,
+            owned_by_me: true,
+            last_modified: DateTime::<FixedOffset>::from(DateTime::UNIX_EPOCH),
+            last_accessed: SystemTime::now(),
+            trashed: false,
+            version: None,
+            parents: Vec::new(),
+            can_edit: true,
+            shortcut_target_id: None,
+            permissions: Permissions::default(),
+            symlink_target: None,
+        };
+
+        let uploaded = webdav.upload(&file_wrapper, None).await.unwrap();
+        assert!(uploaded.version.is_some());
+        let listed = webdav.list_files().await.unwrap();
+        let listed_file = listed.iter().find(|file| file.id == uploaded.id).unwrap();
+        let local_modified = fs::metadata(&path).unwrap().modified().unwrap();
+        assert_eq!(
+            listed_file.modified_time.timestamp() as u64,
+            as_secs(local_modified)
+        );
+        let stored_file_wrapper = FileWrapper {
+            id: uploaded.id.clone(),
+            ..file_wrapper
+        };
+        let downloaded = webdav.download(&stored_file_wrapper).await.unwrap();
+        assert_eq!(downloaded, b"rdrive".to_vec());
+        webdav.trash(&stored_file_wrapper).await.unwrap();
+        let listed = webdav.list_files().await.unwrap();
+        assert!(!listed.iter().any(|file| file.id == uploaded.id));
+    }
+}
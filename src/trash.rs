@@ -0,0 +1,208 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use chrono::{DateTime, Local};
+use log::debug;
+use serde::{Deserialize, Serialize};
+
+/// The directory under the root directory that replaced and removed local files are moved to.
+pub const TRASH_DIRECTORY: &str = ".rdrive-trash";
+const METADATA_EXTENSION: &str = "json";
+
+/// A file in the trash. Each entry is a directory named after when the file was trashed, holding
+/// the file under its original name, next to a JSON file of the same name with this metadata.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct TrashEntry {
+    pub id: String,
+    /// Where the file was before it was trashed.
+    pub path: PathBuf,
+    /// When the file was trashed, in RFC 3339.
+    pub trashed_at: String,
+    pub size: u64,
+}
+
+impl TrashEntry {
+    fn trashed_time(&self) -> SystemTime {
+        DateTime::parse_from_rfc3339(&self.trashed_at)
+            .map(SystemTime::from)
+            .unwrap_or(SystemTime::UNIX_EPOCH)
+    }
+}
+
+/// Moves `path` into the trash under `root_dir`, returning the new entry.
+pub fn move_to_trash(root_dir: &Path, path: &Path) -> io::Result<TrashEntry> {
+    let trash_dir = root_dir.join(TRASH_DIRECTORY);
+    let now = Local::now();
+    let timestamp = now.format("%Y-%m-%dT%H-%M-%S%.6f").to_string();
+    let mut id = timestamp.clone();
+    let mut attempt = 1;
+    while trash_dir.join(&id).exists() {
+        attempt += 1;
+        id = format!("{}-{}", timestamp, attempt);
+    }
+    let name = path
+        .file_name()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "Path has no file name"))?;
+    let entry_dir = trash_dir.join(&id);
+    fs::create_dir_all(&entry_dir)?;
+    let entry = TrashEntry {
+        id,
+        path: path.to_path_buf(),
+        trashed_at: now.to_rfc3339(),
+        size: fs::symlink_metadata(path)?.len(),
+    };
+    fs::rename(path, entry_dir.join(name))?;
+    fs::write(
+        metadata_path(&trash_dir, &entry.id),
+        serde_json::to_vec_pretty(&entry)?,
+    )?;
+    debug!("Moved {} to {}", path.display(), entry_dir.display());
+    Ok(entry)
+}
+
+/// Every entry in the trash under `root_dir`, oldest first.
+pub fn list(root_dir: &Path) -> io::Result<Vec<TrashEntry>> {
+    let trash_dir = root_dir.join(TRASH_DIRECTORY);
+    let mut entries = Vec::new();
+    let dir_entries = match fs::read_dir(&trash_dir) {
+        Ok(dir_entries) => dir_entries,
+        Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(entries),
+        Err(error) => return Err(error),
+    };
+    for dir_entry in dir_entries {
+        let path = dir_entry?.path();
+        if path.extension().and_then(|extension| extension.to_str()) == Some(METADATA_EXTENSION) {
+            entries.push(serde_json::from_slice::<TrashEntry>(&fs::read(path)?)?);
+        }
+    }
+    entries.sort_by(|first, second| {
+        (&first.trashed_at, &first.id).cmp(&(&second.trashed_at, &second.id))
+    });
+    Ok(entries)
+}
+
+/// Puts a trashed file back where it was. Anything that has since been written to that path is
+/// trashed first, so nothing is lost by restoring.
+pub fn restore(root_dir: &Path, entry: &TrashEntry) -> io::Result<()> {
+    let trash_dir = root_dir.join(TRASH_DIRECTORY);
+    let name = entry
+        .path
+        .file_name()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "Path has no file name"))?;
+    if fs::symlink_metadata(&entry.path).is_ok() {
+        move_to_trash(root_dir, &entry.path)?;
+    }
+    if let Some(parent) = entry.path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::rename(trash_dir.join(&entry.id).join(name), &entry.path)?;
+    remove(&trash_dir, entry)
+}
+
+/// Permanently deletes entries trashed longer than `max_age` ago, then the oldest entries until
+/// the trash is no bigger than `max_size` bytes. Returns how many entries were deleted.
+pub fn purge(
+    root_dir: &Path,
+    max_age: Option<Duration>,
+    max_size: Option<u64>,
+    now: SystemTime,
+) -> io::Result<usize> {
+    let trash_dir = root_dir.join(TRASH_DIRECTORY);
+    let entries = list(root_dir)?;
+    let mut total_size: u64 = entries.iter().map(|entry| entry.size).sum();
+    let mut purged = 0;
+    for entry in &entries {
+        let is_expired = max_age.is_some_and(|max_age| {
+            now.duration_since(entry.trashed_time())
+                .is_ok_and(|age| age > max_age)
+        });
+        let is_over_size = max_size.is_some_and(|max_size| total_size > max_size);
+        if !is_expired && !is_over_size {
+            continue;
+        }
+        debug!("Deleting {} from the trash", entry.path.display());
+        remove(&trash_dir, entry)?;
+        total_size -= entry.size;
+        purged += 1;
+    }
+    Ok(purged)
+}
+
+fn remove(trash_dir: &Path, entry: &TrashEntry) -> io::Result<()> {
+    let entry_dir = trash_dir.join(&entry.id);
+    if entry_dir.exists() {
+        fs::remove_dir_all(entry_dir)?;
+    }
+    fs::remove_file(metadata_path(trash_dir, &entry.id))
+}
+
+fn metadata_path(trash_dir: &Path, id: &str) -> PathBuf {
+    trash_dir.join(format!("{}.{}", id, METADATA_EXTENSION))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use super::*;
+
+    #[test]
+    fn restore_should_put_trashed_file_back() {
+        let root_dir = env::temp_dir().join("rdrive-trash-restore-test");
+        let _ = fs::remove_dir_all(&root_dir);
+        let path = root_dir.join("folder").join("file.txt");
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, "old").unwrap();
+
+        let entry = move_to_trash(&root_dir, &path).unwrap();
+        assert!(!path.exists());
+        assert_eq!(entry.size, 3);
+        assert_eq!(list(&root_dir).unwrap(), vec![entry.clone()]);
+
+        fs::write(&path, "new").unwrap();
+        restore(&root_dir, &entry).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "old");
+        let entries = list(&root_dir).unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].path, path);
+        assert_eq!(entries[0].size, 3);
+        fs::remove_dir_all(root_dir).unwrap();
+    }
+
+    #[test]
+    fn purge_should_delete_expired_entries_and_oldest_entries_over_size() {
+        let root_dir = env::temp_dir().join("rdrive-trash-purge-test");
+        let _ = fs::remove_dir_all(&root_dir);
+        fs::create_dir_all(&root_dir).unwrap();
+        for (name, content) in [("first", "1234"), ("second", "1234"), ("third", "12")] {
+            let path = root_dir.join(name);
+            fs::write(&path, content).unwrap();
+            move_to_trash(&root_dir, &path).unwrap();
+        }
+
+        let now = SystemTime::now();
+        assert_eq!(
+            purge(&root_dir, Some(Duration::from_secs(60)), None, now).unwrap(),
+            0
+        );
+        assert_eq!(purge(&root_dir, None, Some(6), now).unwrap(), 1);
+        let remaining: Vec<PathBuf> = list(&root_dir)
+            .unwrap()
+            .into_iter()
+            .map(|entry| entry.path)
+            .collect();
+        assert_eq!(
+            remaining,
+            vec![root_dir.join("second"), root_dir.join("third")]
+        );
+        let later = now + Duration::from_secs(120);
+        assert_eq!(
+            purge(&root_dir, Some(Duration::from_secs(60)), None, later).unwrap(),
+            2
+        );
+        assert!(list(&root_dir).unwrap().is_empty());
+        fs::remove_dir_all(root_dir).unwrap();
+    }
+}
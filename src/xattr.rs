@@ -0,0 +1,71 @@
+use std::io;
+use std::path::Path;
+
+/// The ID of the remote file a local file was synced with.
+pub const ID_ATTRIBUTE: &str = "user.rdrive.id";
+/// The version of the remote file when the local file was last synced.
+pub const VERSION_ATTRIBUTE: &str = "user.rdrive.version";
+
+/// Reads an extended attribute, returning `None` if the file doesn't have it.
+#[cfg(unix)]
+pub fn get(path: &Path, name: &str) -> io::Result<Option<String>> {
+    match ::xattr::get(path, name)? {
+        Some(value) => String::from_utf8(value)
+            .map(Some)
+            .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error)),
+        None => Ok(None),
+    }
+}
+
+#[cfg(unix)]
+pub fn set(path: &Path, name: &str, value: &str) -> io::Result<()> {
+    ::xattr::set(path, name, value.as_bytes())
+}
+
+#[cfg(not(unix))]
+pub fn get(_path: &Path, _name: &str) -> io::Result<Option<String>> {
+    Err(unsupported())
+}
+
+#[cfg(not(unix))]
+pub fn set(_path: &Path, _name: &str, _value: &str) -> io::Result<()> {
+    Err(unsupported())
+}
+
+#[cfg(not(unix))]
+fn unsupported() -> io::Error {
+    io::Error::new(
+        io::ErrorKind::Unsupported,
+        "Extended attributes aren't supported on this platform",
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs;
+
+    use super::*;
+
+    #[test]
+    #[cfg(unix)]
+    fn get_should_read_attribute_written_by_set() {
+        let path = env::temp_dir().join("rdrive-xattr-test");
+        fs::write(&path, "content").unwrap();
+        match set(&path, ID_ATTRIBUTE, "1a2b3c") {
+            Ok(()) => {
+                assert_eq!(
+                    get(&path, ID_ATTRIBUTE).unwrap(),
+                    Some("1a2b3c".to_string())
+                );
+                assert_eq!(get(&path, VERSION_ATTRIBUTE).unwrap(), None);
+            }
+            // Not every file system the tests run on supports extended attributes.
+            Err(error) => println!(
+                "Skipping as extended attributes aren't supported. {}",
+                error
+            ),
+        }
+        fs::remove_file(path).unwrap();
+    }
+}